@@ -4,604 +4,4320 @@
 //! tokio = { version = "1.0", features = ["full"] }
 //! reqwest = { version = "0.11", features = ["json"] }
 //! serde_json = "1.0"
+//! chrono = "0.4"
+//! dirs = "5.0"
+//! jsonwebtoken = "9"
+//! pem = "3"
+//! sha1 = "0.10"
+//! base64 = "0.22"
+//! rand = "0.8"
+//! futures = "0.3"
+//! serde_yaml = "0.9"
+//! regex = "1"
 //! ```
 
 use std::env;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use rand::Rng;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use serde_json::Value;
+use sha1::{Digest, Sha1};
+use tokio::sync::Mutex;
 
-// Resource type mappings
-fn get_resource_mappings() -> HashMap<&'static str, (&'static str, &'static str)> {
-    let mut mappings = HashMap::new();
-    
-    // Network resources
-    mappings.insert("network", ("Microsoft.Network/virtualNetworks", "2023-05-01"));
-    mappings.insert("networks", ("Microsoft.Network/virtualNetworks", "2023-05-01"));
-    mappings.insert("vnet", ("Microsoft.Network/virtualNetworks", "2023-05-01"));
-    mappings.insert("vnets", ("Microsoft.Network/virtualNetworks", "2023-05-01"));
-    mappings.insert("nsg", ("Microsoft.Network/networkSecurityGroups", "2023-05-01"));
-    mappings.insert("nsgs", ("Microsoft.Network/networkSecurityGroups", "2023-05-01"));
-    mappings.insert("publicip", ("Microsoft.Network/publicIPAddresses", "2023-05-01"));
-    mappings.insert("publicips", ("Microsoft.Network/publicIPAddresses", "2023-05-01"));
-    mappings.insert("nic", ("Microsoft.Network/networkInterfaces", "2023-05-01"));
-    mappings.insert("nics", ("Microsoft.Network/networkInterfaces", "2023-05-01"));
-    mappings.insert("loadbalancer", ("Microsoft.Network/loadBalancers", "2023-05-01"));
-    mappings.insert("loadbalancers", ("Microsoft.Network/loadBalancers", "2023-05-01"));
-    
-    // Compute resources
-    mappings.insert("vm", ("Microsoft.Compute/virtualMachines", "2023-03-01"));
-    mappings.insert("vms", ("Microsoft.Compute/virtualMachines", "2023-03-01"));
-    mappings.insert("vmss", ("Microsoft.Compute/virtualMachineScaleSets", "2023-03-01"));
-    mappings.insert("disk", ("Microsoft.Compute/disks", "2023-01-02"));
-    mappings.insert("disks", ("Microsoft.Compute/disks", "2023-01-02"));
-    
-    // Storage resources
-    mappings.insert("storage", ("Microsoft.Storage/storageAccounts", "2023-01-01"));
-    mappings.insert("storageaccount", ("Microsoft.Storage/storageAccounts", "2023-01-01"));
-    mappings.insert("storageaccounts", ("Microsoft.Storage/storageAccounts", "2023-01-01"));
-    
-    // Key Vault
-    mappings.insert("keyvault", ("Microsoft.KeyVault/vaults", "2023-02-01"));
-    mappings.insert("keyvaults", ("Microsoft.KeyVault/vaults", "2023-02-01"));
-    mappings.insert("kv", ("Microsoft.KeyVault/vaults", "2023-02-01"));
-    
-    // App Service
-    mappings.insert("webapp", ("Microsoft.Web/sites", "2022-09-01"));
-    mappings.insert("webapps", ("Microsoft.Web/sites", "2022-09-01"));
-    mappings.insert("appservice", ("Microsoft.Web/sites", "2022-09-01"));
-    mappings.insert("appservices", ("Microsoft.Web/sites", "2022-09-01"));
-    
-    // Database
-    mappings.insert("sql", ("Microsoft.Sql/servers", "2022-05-01-preview"));
-    mappings.insert("sqlserver", ("Microsoft.Sql/servers", "2022-05-01-preview"));
-    mappings.insert("sqlservers", ("Microsoft.Sql/servers", "2022-05-01-preview"));
-    mappings.insert("cosmosdb", ("Microsoft.DocumentDB/databaseAccounts", "2023-04-15"));
-    
-    // Container
-    mappings.insert("aks", ("Microsoft.ContainerService/managedClusters", "2023-05-01"));
-    mappings.insert("acr", ("Microsoft.ContainerRegistry/registries", "2023-01-01-preview"));
-    mappings.insert("containerregistry", ("Microsoft.ContainerRegistry/registries", "2023-01-01-preview"));
-    
-    mappings
+/// `error` object from an ARM error response body
+#[derive(Debug, Clone, PartialEq)]
+struct ArmError {
+    code: String,
+    message: String,
 }
 
-async fn get_azure_token() -> Result<String, String> {
-    // on windows, try powershell approach first
-    if cfg!(windows) {
-        let result = std::process::Command::new("powershell")
-            .args(&["-Command", "az account get-access-token --output json"])
-            .output();
-            
-        if let Ok(output) = result {
-            if output.status.success() {
-                let token_data: Value = serde_json::from_slice(&output.stdout)
-                    .map_err(|_| "Failed to parse token".to_string())?;
-                return Ok(token_data["accessToken"].as_str().unwrap().to_string());
-            }
+impl ArmError {
+    /// parse `{"error": {"code": "...", "message": "..."}}`, ignoring bodies that don't match
+    fn parse(body: &[u8]) -> Option<Self> {
+        let data: Value = serde_json::from_slice(body).ok()?;
+        let error = data.get("error")?;
+        Some(Self {
+            code: error.get("code").and_then(|c| c.as_str()).unwrap_or_default().to_string(),
+            message: error.get("message").and_then(|m| m.as_str()).unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// every way azure_search can fail, each with its own process exit code
+#[derive(Debug)]
+enum AzureSearchError {
+    Usage { message: String },
+    Config { message: String },
+    Auth { message: String },
+    Transport { url: String, source: reqwest::Error },
+    Http { status: u16, url: String, error: Option<ArmError>, correlation_id: Option<String> },
+    UnknownType { resource_type: String },
+    Json { url: String, source: serde_json::Error },
+    NotFound { message: String },
+}
+
+impl AzureSearchError {
+    fn usage(message: impl Into<String>) -> Self {
+        AzureSearchError::Usage { message: message.into() }
+    }
+
+    /// exit codes are part of the interface: shell scripts branch on them
+    fn exit_code(&self) -> i32 {
+        match self {
+            AzureSearchError::Usage { .. } => 2,
+            AzureSearchError::Auth { .. } => 3,
+            AzureSearchError::Transport { .. } => 4,
+            AzureSearchError::Http { .. } => 5,
+            AzureSearchError::UnknownType { .. } => 6,
+            AzureSearchError::Json { .. } => 7,
+            AzureSearchError::Config { .. } => 8,
+            AzureSearchError::NotFound { .. } => 9,
         }
     }
-    
-    // fallback to direct commands
-    let commands = [
-        "az",
-        "az.exe", 
-        "C:\\Program Files (x86)\\Microsoft SDKs\\Azure\\CLI2\\wbin\\az.cmd",
-        "C:\\Program Files\\Microsoft SDKs\\Azure\\CLI2\\wbin\\az.cmd"
-    ];
-    
-    for cmd in &commands {
-        let result = std::process::Command::new(cmd)
-            .args(&["account", "get-access-token", "--output", "json"])
-            .output();
-            
-        match result {
-            Ok(output) => {
-                if output.status.success() {
-                    let token_data: Value = serde_json::from_slice(&output.stdout)
-                        .map_err(|_| "Failed to parse token".to_string())?;
-                    return Ok(token_data["accessToken"].as_str().unwrap().to_string());
-                } else {
-                    let error_msg = String::from_utf8_lossy(&output.stderr);
-                    eprintln!("Command '{}' failed: {}", cmd, error_msg);
-                    continue;
+}
+
+impl fmt::Display for AzureSearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AzureSearchError::Usage { message } => write!(f, "{}", message),
+            AzureSearchError::Config { message } => write!(f, "configuration error: {}", message),
+            AzureSearchError::Auth { message } => write!(f, "authentication failed: {}", message),
+            AzureSearchError::Transport { url, source } => write!(f, "request to {} failed: {}", url, source),
+            AzureSearchError::Http { status, url, error, correlation_id } => {
+                write!(f, "HTTP {} from {}", status, url)?;
+                if let Some(error) = error {
+                    write!(f, ": {}: {}", error.code, error.message)?;
+                }
+                if let Some(correlation_id) = correlation_id {
+                    write!(f, " (correlation id: {})", correlation_id)?;
                 }
+                Ok(())
             }
-            Err(e) => {
-                eprintln!("Failed to execute '{}': {}", cmd, e);
-                continue;
+            AzureSearchError::UnknownType { resource_type } => {
+                write!(f, "unknown resource type: {}. Run with just 'types' to see the aliases, or give the full Namespace/type.", resource_type)
             }
+            AzureSearchError::Json { url, source } => write!(f, "invalid json from {}: {}", url, source),
+            AzureSearchError::NotFound { message } => write!(f, "{}", message),
         }
     }
-    
-    Err("Azure CLI not found. Try running 'where az' to find the correct path.".to_string())
 }
 
-async fn get_resource_json(subscription: &str, rg: &str, resource_type: &str, resource_name: &str) -> Result<Value, String> {
-    let token = get_azure_token().await?;
-    let mappings = get_resource_mappings();
-    
-    let (provider_type, api_version) = mappings.get(resource_type.to_lowercase().as_str())
-        .ok_or_else(|| format!("Unknown resource type: {}. Use 'types' to see available types.", resource_type))?;
-    
-    let url = format!(
-        "https://management.azure.com/subscriptions/{}/resourceGroups/{}/providers/{}/{}?api-version={}",
-        subscription, rg, provider_type, resource_name, api_version
-    );
-
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
-        .bearer_auth(&token)
-        .send()
-        .await
-        .map_err(|_| "Request failed".to_string())?;
-
-    if !response.status().is_success() {
-        return Err(format!("Failed: {}", response.status()));
+impl std::error::Error for AzureSearchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AzureSearchError::Transport { source, .. } => Some(source),
+            AzureSearchError::Json { source, .. } => Some(source),
+            _ => None,
+        }
     }
-
-    response.json().await.map_err(|_| "JSON parse failed".to_string())
 }
 
-async fn list_all_resources(subscription: &str) -> Result<Value, String> {
-    let token = get_azure_token().await?;
-    
-    let url = format!(
-        "https://management.azure.com/subscriptions/{}/resources?api-version=2021-04-01",
-        subscription
-    );
+// Resource type mappings: (category, description, provider type, api-version, aliases)
+const BUILTIN_TYPES: &[(&str, &str, &str, &str, &[&str])] = &[
+    // Network resources
+    ("Network", "Virtual Networks", "Microsoft.Network/virtualNetworks", "2023-05-01", &["network", "networks", "vnet", "vnets"]),
+    ("Network", "Network Security Groups", "Microsoft.Network/networkSecurityGroups", "2023-05-01", &["nsg", "nsgs"]),
+    ("Network", "Public IP Addresses", "Microsoft.Network/publicIPAddresses", "2023-05-01", &["publicip", "publicips"]),
+    ("Network", "Network Interfaces", "Microsoft.Network/networkInterfaces", "2023-05-01", &["nic", "nics"]),
+    ("Network", "Load Balancers", "Microsoft.Network/loadBalancers", "2023-05-01", &["loadbalancer", "loadbalancers"]),
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
-        .bearer_auth(&token)
-        .send()
-        .await
-        .map_err(|_| "Request failed".to_string())?;
+    // Compute resources
+    ("Compute", "Virtual Machines", "Microsoft.Compute/virtualMachines", "2023-03-01", &["vm", "vms"]),
+    ("Compute", "Virtual Machine Scale Sets", "Microsoft.Compute/virtualMachineScaleSets", "2023-03-01", &["vmss", "vmsses", "scaleset", "scalesets"]),
+    ("Compute", "Managed Disks", "Microsoft.Compute/disks", "2023-01-02", &["disk", "disks"]),
 
-    if !response.status().is_success() {
-        return Err(format!("Failed: {}", response.status()));
-    }
+    // Storage resources
+    ("Storage", "Storage Accounts", "Microsoft.Storage/storageAccounts", "2023-01-01", &["storage", "storageaccount", "storageaccounts"]),
 
-    response.json().await.map_err(|_| "JSON parse failed".to_string())
-}
+    // Key Vault
+    ("Security", "Key Vaults", "Microsoft.KeyVault/vaults", "2023-02-01", &["keyvault", "keyvaults", "kv"]),
 
-async fn list_resources_by_type(subscription: &str, resource_type: &str) -> Result<Value, String> {
-    let token = get_azure_token().await?;
-    let mappings = get_resource_mappings();
-    
-    let (provider_type, api_version) = mappings.get(resource_type.to_lowercase().as_str())
-        .ok_or_else(|| format!("Unknown resource type: {}. Use 'types' to see available types.", resource_type))?;
-    
-    let url = format!(
-        "https://management.azure.com/subscriptions/{}/providers/{}?api-version={}",
-        subscription, provider_type, api_version
-    );
+    // App Service
+    ("App Service", "Web Apps", "Microsoft.Web/sites", "2022-09-01", &["webapp", "webapps", "appservice", "appservices"]),
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
-        .bearer_auth(&token)
-        .send()
-        .await
-        .map_err(|_| "Request failed".to_string())?;
+    // Database
+    ("Database", "SQL Servers", "Microsoft.Sql/servers", "2022-05-01-preview", &["sql", "sqlserver", "sqlservers"]),
+    ("Database", "Cosmos DB Accounts", "Microsoft.DocumentDB/databaseAccounts", "2023-04-15", &["cosmosdb", "cosmosdbs", "cosmos"]),
 
-    if !response.status().is_success() {
-        return Err(format!("Failed: {}", response.status()));
-    }
+    // Container
+    ("Container", "Azure Kubernetes Service", "Microsoft.ContainerService/managedClusters", "2023-05-01", &["aks"]),
+    ("Container", "Container Registry", "Microsoft.ContainerRegistry/registries", "2023-01-01-preview", &["acr", "containerregistry"]),
+];
 
-    response.json().await.map_err(|_| "JSON parse failed".to_string())
+/// one resource type and the aliases that select it
+#[derive(Debug, Clone, PartialEq)]
+struct TypeAlias {
+    category: String,
+    description: String,
+    provider_type: String,
+    /// empty when the api-version should come from the provider registry
+    api_version: String,
+    aliases: Vec<String>,
 }
 
-async fn list_resources_in_rg(subscription: &str, rg: &str, resource_type: &str) -> Result<Value, String> {
-    let token = get_azure_token().await?;
-    let mappings = get_resource_mappings();
-    
-    let (provider_type, api_version) = mappings.get(resource_type.to_lowercase().as_str())
-        .ok_or_else(|| format!("Unknown resource type: {}. Use 'types' to see available types.", resource_type))?;
-    
-    let url = format!(
-        "https://management.azure.com/subscriptions/{}/resourceGroups/{}/providers/{}?api-version={}",
-        subscription, rg, provider_type, api_version
-    );
+/// built-in aliases merged with the `aliases` and `apiVersions` sections of the config file
+#[derive(Debug, Clone)]
+struct AliasTable {
+    entries: Vec<TypeAlias>,
+}
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
-        .bearer_auth(&token)
-        .send()
-        .await
-        .map_err(|_| "Request failed".to_string())?;
+impl AliasTable {
+    fn builtin() -> Self {
+        let entries = BUILTIN_TYPES.iter().map(|(category, description, provider_type, api_version, aliases)| TypeAlias {
+            category: category.to_string(),
+            description: description.to_string(),
+            provider_type: provider_type.to_string(),
+            api_version: api_version.to_string(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        }).collect();
+        Self { entries }
+    }
 
-    if !response.status().is_success() {
-        return Err(format!("Failed: {}", response.status()));
+    /// the built-in table with the config file merged over it
+    fn load(config: &Value) -> Result<Self, AzureSearchError> {
+        let mut table = Self::builtin();
+        table.merge(config)?;
+        Ok(table)
     }
 
-    response.json().await.map_err(|_| "JSON parse failed".to_string())
-}
+    /// merge config sections of the form
+    /// `"aliases": {"aci": "Microsoft.ContainerInstance/containerGroups", "pg": {"type": ..., "apiVersion": ..., "category": ..., "description": ...}}`
+    /// and `"apiVersions": {"Microsoft.Sql/servers": "2023-05-01"}`
+    fn merge(&mut self, config: &Value) -> Result<(), AzureSearchError> {
+        let invalid = |message: String| AzureSearchError::Config { message };
 
-fn get_field(data: &Value, field: &str) -> Option<Value> {
-    data.get(field).cloned()
-}
+        if let Some(aliases) = config.get("aliases") {
+            let aliases = aliases.as_object().ok_or_else(|| invalid("config 'aliases' must be an object".to_string()))?;
+            for (alias, spec) in aliases {
+                let (provider_type, spec) = match spec {
+                    Value::String(provider_type) => (provider_type.as_str(), &Value::Null),
+                    Value::Object(fields) => match fields.get("type").and_then(|t| t.as_str()) {
+                        Some(provider_type) => (provider_type, spec),
+                        None => return Err(invalid(format!("config alias '{}' needs a \"type\"", alias))),
+                    },
+                    _ => return Err(invalid(format!("config alias '{}' must be a type string or an object", alias))),
+                };
+                if ResolvedType::parse(provider_type, "").is_none() {
+                    return Err(invalid(format!("config alias '{}': '{}' is not a Namespace/type", alias, provider_type)));
+                }
+                let field = |name: &str| spec.get(name).and_then(|v| v.as_str()).map(|v| v.to_string());
+                self.insert(alias, provider_type, field("apiVersion"), field("category"), field("description"));
+            }
+        }
 
-fn search_json(data: &Value, term: &str) -> Value {
-    let term_lower = term.to_lowercase();
-    let mut results = Vec::new();
-    
-    // if data is a list of resources (from list_resources), search each resource
-    if let Some(resources) = data.get("value").and_then(|v| v.as_array()) {
-        for resource in resources {
-            if resource_contains_term(resource, &term_lower) {
-                results.push(resource.clone());
+        if let Some(versions) = config.get("apiVersions") {
+            let versions = versions.as_object().ok_or_else(|| invalid("config 'apiVersions' must be an object".to_string()))?;
+            for (provider_type, api_version) in versions {
+                let api_version = api_version.as_str()
+                    .ok_or_else(|| invalid(format!("config api-version for '{}' must be a string", provider_type)))?;
+                match self.entries.iter_mut().find(|e| e.provider_type.eq_ignore_ascii_case(provider_type)) {
+                    Some(entry) => entry.api_version = api_version.to_string(),
+                    None => return Err(invalid(format!("config apiVersions: no alias uses '{}'", provider_type))),
+                }
             }
         }
-        return Value::Array(results);
-    }
-    
-    // if data is a single resource, search within it and return the whole resource if match found
-    if resource_contains_term(data, &term_lower) {
-        return data.clone();
+        Ok(())
     }
-    
-    // no matches found
-    serde_json::json!([])
-}
 
-fn subsearch_json(data: &Value, term: &str) -> Value {
-    let term_lower = term.to_lowercase();
-    let mut results = serde_json::Map::new();
-    
-    // if data is a list of resources (from list_resources), search each resource
-    if let Some(resources) = data.get("value").and_then(|v| v.as_array()) {
-        for (resource_index, resource) in resources.iter().enumerate() {
-            let resource_name = resource.get("name")
-                .and_then(|n| n.as_str())
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| format!("resource_{}", resource_index));
-            
-            subsearch_recursive_flat(resource, &resource_name, "", &term_lower, &mut results);
+    /// point `alias` at `provider_type`, taking it away from whichever type had it before
+    fn insert(&mut self, alias: &str, provider_type: &str, api_version: Option<String>, category: Option<String>, description: Option<String>) {
+        let alias = alias.to_lowercase();
+        for entry in &mut self.entries {
+            entry.aliases.retain(|a| *a != alias);
         }
-        return Value::Object(results);
-    }
-    
-    // if data is a single resource, search within it
-    subsearch_recursive_flat(data, "resource", "", &term_lower, &mut results);
-    Value::Object(results)
-}
 
-fn subsearch_recursive_flat(data: &Value, resource_name: &str, path: &str, term: &str, results: &mut serde_json::Map<String, Value>) {
-    match data {
-        Value::Object(map) => {
-            for (key, value) in map {
-                let current_path = if path.is_empty() { 
-                    format!("{}.{}", resource_name, key)
-                } else { 
-                    format!("{}.{}", path, key) 
-                };
-                
-                // check if key contains search term
-                if key.to_lowercase().contains(term) {
-                    results.insert(current_path.clone(), value.clone());
-                }
-                
-                // check if value (as string) contains search term
-                if let Value::String(s) = value {
-                    if s.to_lowercase().contains(term) {
-                        results.insert(current_path.clone(), value.clone());
-                    }
-                }
-                
-                // recurse into nested objects/arrays
-                subsearch_recursive_flat(value, resource_name, &current_path, term, results);
+        let index = match self.entries.iter().position(|e| e.provider_type.eq_ignore_ascii_case(provider_type)) {
+            Some(index) => index,
+            None => {
+                self.entries.push(TypeAlias {
+                    category: "Custom".to_string(),
+                    description: provider_type.to_string(),
+                    provider_type: provider_type.to_string(),
+                    api_version: String::new(),
+                    aliases: Vec::new(),
+                });
+                self.entries.len() - 1
             }
+        };
+
+        let entry = &mut self.entries[index];
+        entry.aliases.push(alias);
+        if let Some(api_version) = api_version {
+            entry.api_version = api_version;
         }
-        Value::Array(arr) => {
-            for (index, item) in arr.iter().enumerate() {
-                let current_path = if path.is_empty() { 
-                    format!("{}[{}]", resource_name, index)
-                } else { 
-                    format!("{}[{}]", path, index) 
-                };
-                subsearch_recursive_flat(item, resource_name, &current_path, term, results);
-            }
+        if let Some(category) = category {
+            entry.category = category;
         }
-        _ => {}
+        if let Some(description) = description {
+            entry.description = description;
+        }
+        self.entries.retain(|e| !e.aliases.is_empty());
     }
-}
 
-fn resource_contains_term(resource: &Value, term: &str) -> bool {
-    search_recursive_bool(resource, term)
-}
+    fn lookup(&self, alias: &str) -> Option<&TypeAlias> {
+        let alias = alias.to_lowercase();
+        self.entries.iter().find(|e| e.aliases.contains(&alias))
+    }
 
-fn search_recursive_bool(data: &Value, term: &str) -> bool {
-    match data {
-        Value::Object(map) => {
-            for (key, value) in map {
-                // check if key contains search term
-                if key.to_lowercase().contains(term) {
-                    return true;
-                }
-                
-                // check if value (as string) contains search term
-                if let Value::String(s) = value {
-                    if s.to_lowercase().contains(term) {
-                        return true;
-                    }
-                }
-                
-                // recurse into nested objects/arrays
-                if search_recursive_bool(value, term) {
-                    return true;
-                }
+    fn print(&self) {
+        println!("Available resource types:");
+        let mut categories: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            if !categories.contains(&entry.category.as_str()) {
+                categories.push(&entry.category);
             }
         }
-        Value::Array(arr) => {
-            for item in arr {
-                if search_recursive_bool(item, term) {
-                    return true;
-                }
+
+        for category in categories {
+            println!();
+            println!("{}:", category);
+            for entry in self.entries.iter().filter(|e| e.category == category) {
+                println!("  {} - {}", entry.aliases.join(", "), entry.description);
             }
         }
-        _ => {}
+        println!();
+        println!("Any other type can be given in full, e.g. Microsoft.ContainerInstance/containerGroups");
     }
-    false
 }
 
-fn print_available_types() {
-    println!("Available resource types:");
-    println!();
-    println!("Network:");
-    println!("  network, networks, vnet, vnets - Virtual Networks");
-    println!("  nsg, nsgs - Network Security Groups");
-    println!("  publicip, publicips - Public IP Addresses");
-    println!("  nic, nics - Network Interfaces");
-    println!("  loadbalancer, loadbalancers - Load Balancers");
-    println!();
-    println!("Compute:");
-    println!("  vm, vms - Virtual Machines");
-    println!("  vmss - Virtual Machine Scale Sets");
-    println!("  disk, disks - Managed Disks");
-    println!();
-    println!("Storage:");
-    println!("  storage, storageaccount, storageaccounts - Storage Accounts");
-    println!();
-    println!("Security:");
-    println!("  keyvault, keyvaults, kv - Key Vaults");
-    println!();
-    println!("App Service:");
-    println!("  webapp, webapps, appservice, appservices - Web Apps");
-    println!();
-    println!("Database:");
-    println!("  sql, sqlserver, sqlservers - SQL Servers");
-    println!("  cosmosdb - Cosmos DB Accounts");
-    println!();
-    println!("Container:");
-    println!("  aks - Azure Kubernetes Service");
-    println!("  acr, containerregistry - Container Registry");
+/// how long provider api-versions are trusted before the registry is asked again
+const API_VERSION_CACHE_TTL_SECS: u64 = 7 * 24 * 3600;
+
+/// a resource type resolved to its provider namespace, type segments and api-version
+#[derive(Debug, Clone, PartialEq)]
+struct ResolvedType {
+    namespace: String,
+    types: Vec<String>,
+    api_version: String,
 }
 
-#[tokio::main]
-async fn main() {
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() < 2 {
-        eprintln!("Usage: {} <subscription> [all|types|resource-type|resource-group] [resource-name] [field|search:term|subsearch:term]", args[0]);
-        eprintln!("Examples:");
-        eprintln!("  {} 12345", args[0]);
-        eprintln!("  {} 12345 all", args[0]);
-        eprintln!("  {} 12345 types", args[0]);
-        eprintln!("  {} 12345 network", args[0]);
-        eprintln!("  {} 12345 storage", args[0]);
-        eprintln!("  {} 12345 search:Standard", args[0]);
-        eprintln!("  {} 12345 subsearch:size", args[0]);
-        eprintln!("  {} 12345 myRG network", args[0]);
-        eprintln!("  {} 12345 myRG network myVNet", args[0]);
-        eprintln!("  {} 12345 myRG network myVNet name", args[0]);
-        eprintln!("  {} 12345 myRG network myVNet search:subnet", args[0]);
-        eprintln!("  {} 12345 myRG network myVNet subsearch:address", args[0]);
-        return;
+impl ResolvedType {
+    /// split "Microsoft.Network/virtualNetworks/subnets" into namespace and type segments
+    fn parse(full_type: &str, api_version: &str) -> Option<Self> {
+        let mut segments = full_type.trim_matches('/').split('/').map(|s| s.trim());
+        let namespace = segments.next().filter(|ns| ns.contains('.'))?.to_string();
+        let types: Vec<String> = segments.map(|s| s.to_string()).collect();
+        if types.is_empty() || types.iter().any(|t| t.is_empty()) {
+            return None;
+        }
+        Some(Self { namespace, types, api_version: api_version.to_string() })
     }
 
-    let subscription = &args[1];
+    fn full_name(&self) -> String {
+        format!("{}/{}", self.namespace, self.types.join("/"))
+    }
 
-    // show available types
-    if args.len() == 3 && args[2] == "types" {
-        print_available_types();
-        return;
+    fn is_child(&self) -> bool {
+        self.types.len() > 1
     }
 
-    // check if second argument is a search or subsearch
-    if args.len() == 3 && (args[2].starts_with("search:") || args[2].starts_with("subsearch:")) {
-        let is_subsearch = args[2].starts_with("subsearch:");
-        let search_term = if is_subsearch { &args[2][10..] } else { &args[2][7..] };
-        
-        // get all resources and search across them
-        match list_all_resources(subscription).await {
-            Ok(data) => {
-                let results = if is_subsearch {
-                    subsearch_json(&data, search_term)
-                } else {
-                    search_json(&data, search_term)
-                };
-                
-                if (is_subsearch && results.as_object().map_or(true, |obj| obj.is_empty())) ||
-                   (!is_subsearch && results.as_array().map_or(true, |arr| arr.is_empty())) {
-                    println!("No resources found containing '{}'", search_term);
-                } else {
-                    println!("{}", serde_json::to_string_pretty(&results).unwrap());
-                }
-            }
-            Err(e) => {
-                eprintln!("Error: {}", e);
-            }
-        }
-        return;
+    /// lowercase registry keys to try for the api-version: the type itself, then each
+    /// parent up to the top-level type, since the registry often leaves out child types
+    fn registry_keys(&self) -> Vec<String> {
+        (1..=self.types.len()).rev()
+            .map(|depth| format!("{}/{}", self.namespace, self.types[..depth].join("/")).to_lowercase())
+            .collect()
     }
 
-    // if only subscription provided, list all resources by default
-    if args.len() == 2 {
-        match list_all_resources(subscription).await {
-            Ok(data) => {
-                println!("{}", serde_json::to_string_pretty(&data).unwrap());
-            }
-            Err(e) => {
-                eprintln!("Error: {}", e);
+    /// provider path with `names` interleaved into the type segments, e.g.
+    /// "Microsoft.Network/virtualNetworks/vnet1/subnets/default"; one name short of
+    /// the type depth gives the child collection under that parent
+    fn path(&self, names: &[&str]) -> Result<String, AzureSearchError> {
+        if names.len() != self.types.len() && names.len() + 1 != self.types.len() {
+            return Err(AzureSearchError::usage(format!(
+                "{} needs {} name(s) separated by '/', got '{}'",
+                self.full_name(),
+                self.types.len(),
+                names.join("/")
+            )));
+        }
+
+        let mut path = self.namespace.clone();
+        for (index, type_segment) in self.types.iter().enumerate() {
+            path.push('/');
+            path.push_str(type_segment);
+            if let Some(name) = names.get(index) {
+                path.push('/');
+                path.push_str(name);
             }
         }
-        return;
+        Ok(path)
     }
+}
 
-    // handle specific commands: all, or resource type, or resource group name
-    if args.len() == 3 && !args[2].starts_with("search:") && !args[2].starts_with("subsearch:") {
-        let command = &args[2];
-        
-        if command == "all" {
-            match list_all_resources(subscription).await {
-                Ok(data) => {
-                    println!("{}", serde_json::to_string_pretty(&data).unwrap());
-                }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                }
-            }
-            return;
+/// a parsed ARM id: a subscription, a resource group, or a provider resource with
+/// any number of child levels, e.g.
+/// "/subscriptions/s/resourceGroups/rg/providers/Microsoft.Network/networkInterfaces/nic1/ipConfigurations/ipconfig1"
+#[derive(Debug, Clone, PartialEq)]
+struct ResourceId {
+    subscription: String,
+    resource_group: Option<String>,
+    namespace: Option<String>,
+    /// type segments below the namespace, each paired with the name at the same index
+    types: Vec<String>,
+    names: Vec<String>,
+}
+
+impl ResourceId {
+    fn looks_like(text: &str) -> bool {
+        let prefix = "/subscriptions/";
+        text.len() > prefix.len() && text.get(..prefix.len()).is_some_and(|p| p.eq_ignore_ascii_case(prefix))
+    }
+
+    fn parse(id: &str) -> Result<Self, AzureSearchError> {
+        let invalid = |reason: &str| AzureSearchError::usage(format!("Invalid resource id '{}': {}", id, reason));
+        let segments: Vec<&str> = id.trim().trim_matches('/').split('/').collect();
+        if segments.iter().any(|s| s.is_empty()) {
+            return Err(invalid("empty segment"));
         }
-        
-        let mappings = get_resource_mappings();
-        
-        // check if it's a known resource type
-        if mappings.contains_key(command.to_lowercase().as_str()) {
-            match list_resources_by_type(subscription, command).await {
-                Ok(data) => {
-                    println!("{}", serde_json::to_string_pretty(&data).unwrap());
-                }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                }
+
+        let (subscription, mut rest) = match segments.as_slice() {
+            [keyword, subscription, rest @ ..] if keyword.eq_ignore_ascii_case("subscriptions") => (subscription.to_string(), rest),
+            _ => return Err(invalid("expected /subscriptions/<id>/...")),
+        };
+
+        let mut resource_group = None;
+        if let [keyword, rg, tail @ ..] = rest {
+            if keyword.eq_ignore_ascii_case("resourceGroups") {
+                resource_group = Some(rg.to_string());
+                rest = tail;
             }
-            return;
-        } else {
-            // treat as resource group name - list all resources in RG
-            let rg = command;
-            let url = format!(
-                "https://management.azure.com/subscriptions/{}/resourceGroups/{}/resources?api-version=2021-04-01",
-                subscription, rg
-            );
-            
-            let token = match get_azure_token().await {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("Error getting token: {}", e);
-                    return;
+        }
+
+        let mut resource = ResourceId { subscription, resource_group, namespace: None, types: Vec::new(), names: Vec::new() };
+        match rest {
+            [] => return Ok(resource),
+            [keyword, namespace, pairs @ ..] if keyword.eq_ignore_ascii_case("providers") && namespace.contains('.') => {
+                if pairs.is_empty() || pairs.len() % 2 != 0 {
+                    return Err(invalid("every type segment needs a name after it"));
                 }
-            };
-            
-            let client = reqwest::Client::new();
-            match client.get(&url).bearer_auth(&token).send().await {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        match response.json::<Value>().await {
-                            Ok(data) => {
-                                println!("{}", serde_json::to_string_pretty(&data).unwrap());
-                            }
-                            Err(_) => {
-                                eprintln!("Error: Failed to parse JSON response");
-                            }
-                        }
-                    } else {
-                        eprintln!("Error: Failed to list resources in RG '{}': {}", rg, response.status());
-                    }
+                if pairs.iter().step_by(2).any(|t| t.eq_ignore_ascii_case("providers")) {
+                    return Err(invalid("extension resources (a second /providers/) are not supported"));
                 }
-                Err(_) => {
-                    eprintln!("Error: Request failed");
+                resource.namespace = Some(namespace.to_string());
+                for pair in pairs.chunks(2) {
+                    resource.types.push(pair[0].to_string());
+                    resource.names.push(pair[1].to_string());
                 }
             }
-            return;
+            _ => return Err(invalid("expected /resourceGroups/<name> or /providers/<Namespace>/<type>/<name>")),
         }
+        Ok(resource)
     }
 
-    // handle: subscription rg resource_type
-    if args.len() == 4 {
-        let rg = &args[2];
-        let resource_type = &args[3];
-        
-        match list_resources_in_rg(subscription, rg, resource_type).await {
-            Ok(data) => {
-                println!("{}", serde_json::to_string_pretty(&data).unwrap());
-            }
-            Err(e) => {
-                eprintln!("Error: {}", e);
-            }
-        }
-        return;
+    /// "Microsoft.Network/networkInterfaces/ipConfigurations", for a resource id
+    fn resource_type(&self) -> Option<String> {
+        self.namespace.as_ref().map(|ns| format!("{}/{}", ns, self.types.join("/")))
     }
 
-    // need at least subscription, rg, resource_type, resource_name for specific resource queries
-    if args.len() < 5 {
-        eprintln!("Error: Need subscription, resource-group, resource-type, and resource-name for specific resource queries");
-        eprintln!("Usage: {} <subscription> <resource-group> <resource-type> <resource-name> [field|search:term|subsearch:term]", args[0]);
-        eprintln!("Or use: {} <subscription> search:term", args[0]);
-        eprintln!("Or use: {} <subscription> subsearch:term", args[0]);
-        return;
+    /// "nic1/ipconfig1", the form `get --name` takes for child types
+    fn name(&self) -> Option<String> {
+        self.namespace.as_ref().map(|_| self.names.join("/"))
     }
+}
 
-    let rg = &args[2];
-    let resource_type = &args[3];
-    let resource_name = &args[4];
-    let query = args.get(5);
-
-    // get the specific resource data
-    let data = match get_resource_json(subscription, rg, resource_type, resource_name).await {
-        Ok(d) => d,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return;
-        }
-    };
-
-    // handle different query types for specific resource
-    match query {
-        None => {
-            // no query - return full JSON
-            println!("{}", serde_json::to_string_pretty(&data).unwrap());
-        }
-        Some(q) if q.starts_with("search:") => {
-            // search query on specific resource - returns full resource
-            let search_term = &q[7..]; // remove "search:" prefix
-            let results = search_json(&data, search_term);
-            if results.as_array().map_or(true, |arr| arr.is_empty()) && !results.is_object() {
-                println!("No matches found for '{}'", search_term);
-            } else {
-                println!("{}", serde_json::to_string_pretty(&results).unwrap());
-            }
-        }
-        Some(q) if q.starts_with("subsearch:") => {
-            // subsearch query on specific resource - returns just matching fields
-            let search_term = &q[10..]; // remove "subsearch:" prefix
-            let results = subsearch_json(&data, search_term);
-            if results.as_object().map_or(true, |obj| obj.is_empty()) {
-                println!("No matches found for '{}'", search_term);
-            } else {
-                println!("{}", serde_json::to_string_pretty(&results).unwrap());
-            }
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "/subscriptions/{}", self.subscription)?;
+        if let Some(rg) = &self.resource_group {
+            write!(f, "/resourceGroups/{}", rg)?;
         }
-        Some(field) => {
-            // field query on specific resource
-            match get_field(&data, field) {
-                Some(value) => println!("{}", serde_json::to_string_pretty(&value).unwrap()),
-                None => eprintln!("Field '{}' not found", field),
+        if let Some(namespace) = &self.namespace {
+            write!(f, "/providers/{}", namespace)?;
+            for (type_segment, name) in self.types.iter().zip(&self.names) {
+                write!(f, "/{}/{}", type_segment, name)?;
             }
         }
+        Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// choose the newest stable api-version, falling back to the newest preview
+fn pick_api_version(versions: &[&str]) -> Option<String> {
+    let is_stable = |v: &&&str| !v.contains("preview") && !v.contains("alpha") && !v.contains("beta");
+    versions.iter().filter(is_stable).max()
+        .or_else(|| versions.iter().max())
+        .map(|v| v.to_string())
+}
 
-    #[test]
-    fn test_get_field() {
-        let data = serde_json::json!({
+/// api-versions for every type in a `/providers/{namespace}` response, keyed by lowercase full type
+fn provider_api_versions(namespace: &str, provider: &Value) -> HashMap<String, String> {
+    provider.get("resourceTypes")
+        .and_then(|t| t.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let resource_type = entry.get("resourceType")?.as_str()?;
+            let versions: Vec<&str> = entry.get("apiVersions")?.as_array()?.iter().filter_map(|v| v.as_str()).collect();
+            Some((format!("{}/{}", namespace, resource_type).to_lowercase(), pick_api_version(&versions)?))
+        })
+        .collect()
+}
+
+/// on-disk api-version cache: `"arm endpoint|full type"` -> {apiVersion, fetchedAt}
+struct ApiVersionCache {
+    path: PathBuf,
+}
+
+impl ApiVersionCache {
+    fn open() -> Option<Self> {
+        dirs::cache_dir().map(|dir| Self { path: dir.join("azure_search").join("api-versions.json") })
+    }
+
+    fn read_all(&self) -> serde_json::Map<String, Value> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+            .and_then(|v| v.as_object().cloned())
+            .unwrap_or_default()
+    }
+
+    fn load(&self, key: &str) -> Option<String> {
+        let entries = self.read_all();
+        let entry = entries.get(key)?;
+        let fetched_at = entry.get("fetchedAt")?.as_u64()?;
+        if fetched_at + API_VERSION_CACHE_TTL_SECS < unix_now() {
+            return None;
+        }
+        entry.get("apiVersion")?.as_str().map(|v| v.to_string())
+    }
+
+    fn store(&self, arm_endpoint: &str, versions: &HashMap<String, String>) -> std::io::Result<()> {
+        let mut entries = self.read_all();
+        for (resource_type, api_version) in versions {
+            entries.insert(format!("{}|{}", arm_endpoint, resource_type), serde_json::json!({
+                "apiVersion": api_version,
+                "fetchedAt": unix_now(),
+            }));
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // write a sibling file and rename it over the cache so concurrent runs never see a partial file
+        let temp_path = self.path.with_extension(format!("json.{}.tmp", std::process::id()));
+        std::fs::write(&temp_path, serde_json::to_string(&Value::Object(entries)).unwrap())?;
+        std::fs::rename(&temp_path, &self.path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp_path);
+        })
+    }
+}
+
+/// resolve an alias such as "vnet" or any fully qualified "Namespace/type[/child]"
+///
+/// aliases keep their pinned api-version; other types, and aliases without one,
+/// get the newest stable version from the provider registry, cached on disk and
+/// fetched at most once per namespace by each client; child types the registry
+/// does not list use their parent type's version, as `az resource` does
+async fn resolve_resource_type(client: &ArmClient, aliases: &AliasTable, resource_type: &str) -> Result<ResolvedType, AzureSearchError> {
+    let unknown = || AzureSearchError::UnknownType { resource_type: resource_type.to_string() };
+    let full_type = aliases.lookup(resource_type).map_or(resource_type, |entry| entry.provider_type.as_str());
+    let mut resolved = ResolvedType::parse(full_type, "").ok_or_else(unknown)?;
+    if let Some(entry) = aliases.lookup(resource_type).filter(|e| !e.api_version.is_empty()) {
+        resolved.api_version = entry.api_version.clone();
+        return Ok(resolved);
+    }
+
+    let keys = resolved.registry_keys();
+    let pick = |versions: &HashMap<String, String>| keys.iter().find_map(|key| versions.get(key).cloned());
+    let namespace = resolved.namespace.to_lowercase();
+    // held across the lookup so concurrent resolves of one namespace share a single request
+    let mut memo = client.api_versions.lock().await;
+    if let Some(versions) = memo.get(&namespace) {
+        resolved.api_version = pick(versions).ok_or_else(unknown)?;
+        return Ok(resolved);
+    }
+
+    let cache = client.api_version_cache.as_ref();
+    let cached = cache.and_then(|c| keys.iter().find_map(|key| c.load(&format!("{}|{}", client.arm_endpoint, key))));
+    if let Some(api_version) = cached {
+        resolved.api_version = api_version;
+        return Ok(resolved);
+    }
+
+    let url = format!("{}/providers/{}?api-version=2021-04-01", client.arm_endpoint, resolved.namespace);
+    let provider = match client.get_json(&url).await {
+        Err(AzureSearchError::Http { status: 404, .. }) => return Err(unknown()),
+        other => other?,
+    };
+
+    let versions = provider_api_versions(&resolved.namespace, &provider);
+    if let Some(cache) = cache {
+        if let Err(e) = cache.store(&client.arm_endpoint, &versions) {
+            eprintln!("Warning: could not write api-version cache '{}': {}", cache.path.display(), e);
+        }
+    }
+
+    let api_version = pick(&versions);
+    memo.insert(namespace, versions);
+    resolved.api_version = api_version.ok_or_else(unknown)?;
+    Ok(resolved)
+}
+
+/// refresh tokens this many seconds before they actually expire
+const TOKEN_REFRESH_MARGIN_SECS: u64 = 300;
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// bearer token together with its absolute expiry in unix seconds
+#[derive(Debug, Clone, PartialEq)]
+struct AccessToken {
+    token: String,
+    expires_on: u64,
+}
+
+impl AccessToken {
+    fn is_fresh(&self) -> bool {
+        unix_now() + TOKEN_REFRESH_MARGIN_SECS < self.expires_on
+    }
+}
+
+/// parse `az account get-access-token` output
+///
+/// newer CLI versions report `expires_on` as unix seconds, older ones only
+/// have `expiresOn` as a local timestamp like "2024-01-01 12:00:00.000000"
+fn parse_cli_token(token_data: &Value) -> Result<AccessToken, String> {
+    let token = token_data.get("accessToken")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| "Token response has no accessToken".to_string())?
+        .to_string();
+
+    let expires_on = match token_data.get("expires_on").and_then(|v| v.as_u64()) {
+        Some(epoch) => epoch,
+        None => {
+            let local = token_data.get("expiresOn")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| "Token response has no expiry".to_string())?;
+            let naive = NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M:%S%.f")
+                .map_err(|_| format!("Unrecognised token expiry '{}'", local))?;
+            let timestamp = Local.from_local_datetime(&naive)
+                .earliest()
+                .ok_or_else(|| format!("Unrecognised token expiry '{}'", local))?
+                .timestamp();
+            u64::try_from(timestamp).unwrap_or(0)
+        }
+    };
+
+    Ok(AccessToken { token, expires_on })
+}
+
+async fn get_azure_token(tenant: Option<&str>, audience: &str) -> Result<AccessToken, String> {
+    let mut az_args = vec!["account", "get-access-token", "--output", "json", "--resource", audience];
+    if let Some(tenant) = tenant {
+        az_args.extend(["--tenant", tenant]);
+    }
+
+    // run az directly so the tenant and audience are passed as arguments, never through a shell;
+    // on windows the CLI is a batch file, which needs its full name
+    let commands = [
+        "az",
+        "az.exe",
+        "az.cmd",
+        "C:\\Program Files (x86)\\Microsoft SDKs\\Azure\\CLI2\\wbin\\az.cmd",
+        "C:\\Program Files\\Microsoft SDKs\\Azure\\CLI2\\wbin\\az.cmd"
+    ];
+    
+    for cmd in &commands {
+        let result = std::process::Command::new(cmd)
+            .args(&az_args)
+            .output();
+            
+        match result {
+            Ok(output) => {
+                if output.status.success() {
+                    let token_data: Value = serde_json::from_slice(&output.stdout)
+                        .map_err(|_| "Failed to parse token".to_string())?;
+                    return parse_cli_token(&token_data);
+                } else {
+                    let error_msg = String::from_utf8_lossy(&output.stderr);
+                    eprintln!("Command '{}' failed: {}", cmd, error_msg);
+                    continue;
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to execute '{}': {}", cmd, e);
+                continue;
+            }
+        }
+    }
+    
+    Err("Azure CLI not found. Try running 'where az' to find the correct path.".to_string())
+}
+
+const DEFAULT_AUTHORITY: &str = "https://login.microsoftonline.com";
+
+const DEFAULT_IMDS_ENDPOINT: &str = "http://169.254.169.254";
+
+const JWT_BEARER_ASSERTION: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// how `--auth` selects token sources
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum AuthMode {
+    /// every credential configured through the environment, then the Azure CLI
+    #[default]
+    Auto,
+    ClientSecret,
+    ClientCertificate,
+    WorkloadIdentity,
+    ManagedIdentity,
+    AzureCli,
+}
+
+impl AuthMode {
+    fn parse(mode: &str) -> Result<Self, AzureSearchError> {
+        match mode.to_lowercase().as_str() {
+            "auto" => Ok(AuthMode::Auto),
+            "secret" | "client-secret" => Ok(AuthMode::ClientSecret),
+            "certificate" | "cert" | "client-certificate" => Ok(AuthMode::ClientCertificate),
+            "workload" | "workload-identity" => Ok(AuthMode::WorkloadIdentity),
+            "msi" | "managed-identity" | "imds" => Ok(AuthMode::ManagedIdentity),
+            "cli" | "az" => Ok(AuthMode::AzureCli),
+            _ => Err(AzureSearchError::usage(format!("Unknown auth mode '{}'. Use auto, secret, certificate, workload, msi or cli.", mode))),
+        }
+    }
+}
+
+/// a single token source in the credential chain
+#[derive(Debug, Clone)]
+enum Credential {
+    ClientSecret { tenant: String, client_id: String, secret: String },
+    ClientCertificate { tenant: String, client_id: String, certificate_path: PathBuf },
+    /// federated token projected into the pod, e.g. by AKS workload identity
+    WorkloadIdentity { tenant: String, client_id: String, token_file: PathBuf },
+    /// instance metadata service on Azure VMs and container groups; `probe`
+    /// shortens the timeout when we are only guessing that IMDS exists
+    ManagedIdentity { endpoint: String, client_id: Option<String>, probe: bool },
+    AzureCli { tenant: Option<String> },
+}
+
+impl Credential {
+    fn name(&self) -> &'static str {
+        match self {
+            Credential::ClientSecret { .. } => "client secret",
+            Credential::ClientCertificate { .. } => "client certificate",
+            Credential::WorkloadIdentity { .. } => "workload identity",
+            Credential::ManagedIdentity { .. } => "managed identity",
+            Credential::AzureCli { .. } => "Azure CLI",
+        }
+    }
+
+    /// the kind of credential and the identity it signs in as, e.g. "secret:<client id>"
+    fn cache_id(&self) -> String {
+        match self {
+            Credential::ClientSecret { client_id, .. } => format!("secret:{}", client_id),
+            Credential::ClientCertificate { client_id, .. } => format!("certificate:{}", client_id),
+            Credential::WorkloadIdentity { client_id, .. } => format!("workload:{}", client_id),
+            Credential::ManagedIdentity { client_id, .. } => format!("msi:{}", client_id.as_deref().unwrap_or("system")),
+            Credential::AzureCli { .. } => "cli".to_string(),
+        }
+    }
+
+    async fn get_token(&self, http: &reqwest::Client, authority: &str, audience: &str) -> Result<AccessToken, String> {
+        match self {
+            Credential::ClientSecret { tenant, client_id, secret } => {
+                request_client_credentials(http, authority, tenant, client_id, audience, &[
+                    ("client_secret", secret.as_str()),
+                ]).await
+            }
+            Credential::ClientCertificate { tenant, client_id, certificate_path } => {
+                let endpoint = token_endpoint(authority, tenant);
+                let assertion = build_client_assertion(certificate_path, client_id, &endpoint)?;
+                request_client_credentials(http, authority, tenant, client_id, audience, &[
+                    ("client_assertion_type", JWT_BEARER_ASSERTION),
+                    ("client_assertion", assertion.as_str()),
+                ]).await
+            }
+            Credential::WorkloadIdentity { tenant, client_id, token_file } => {
+                // the file is rotated by the platform, so read it on every request
+                let assertion = std::fs::read_to_string(token_file)
+                    .map_err(|e| format!("Cannot read federated token '{}': {}", token_file.display(), e))?;
+                request_client_credentials(http, authority, tenant, client_id, audience, &[
+                    ("client_assertion_type", JWT_BEARER_ASSERTION),
+                    ("client_assertion", assertion.trim()),
+                ]).await
+            }
+            Credential::ManagedIdentity { endpoint, client_id, probe } => {
+                request_imds_token(http, endpoint, client_id.as_deref(), audience, *probe).await
+            }
+            Credential::AzureCli { tenant } => get_azure_token(tenant.as_deref(), audience).await,
+        }
+    }
+}
+
+/// build the ordered list of credentials to try for the selected auth mode
+///
+/// service principals are configured the same way as the Azure SDKs:
+/// AZURE_TENANT_ID, AZURE_CLIENT_ID and either AZURE_CLIENT_SECRET or
+/// AZURE_CLIENT_CERTIFICATE_PATH (a PEM file holding the key and certificate)
+///
+/// workload identity uses AZURE_TENANT_ID, AZURE_CLIENT_ID and AZURE_FEDERATED_TOKEN_FILE;
+/// managed identity picks a user-assigned identity from `identity_client_id`,
+/// falling back to AZURE_CLIENT_ID, and uses the system-assigned one otherwise
+///
+/// when guessing, the IMDS probe only runs ahead of the Azure CLI if something
+/// points at IMDS (an explicit identity or IMDS endpoint), and then without the
+/// short probe timeout; otherwise it is the last resort after the CLI
+fn credential_chain(
+    mode: AuthMode,
+    tenant: Option<&str>,
+    imds_endpoint: &str,
+    identity_client_id: Option<&str>,
+) -> Result<Vec<Credential>, String> {
+    let client_id = env_value("AZURE_CLIENT_ID");
+
+    let client_secret = match (tenant, &client_id, env_value("AZURE_CLIENT_SECRET")) {
+        (Some(tenant), Some(client_id), Some(secret)) => Some(Credential::ClientSecret {
+            tenant: tenant.to_string(),
+            client_id: client_id.clone(),
+            secret,
+        }),
+        _ => None,
+    };
+
+    let client_certificate = match (tenant, &client_id, env_value("AZURE_CLIENT_CERTIFICATE_PATH")) {
+        (Some(tenant), Some(client_id), Some(path)) => Some(Credential::ClientCertificate {
+            tenant: tenant.to_string(),
+            client_id: client_id.clone(),
+            certificate_path: PathBuf::from(path),
+        }),
+        _ => None,
+    };
+
+    let workload_identity = match (tenant, &client_id, env_value("AZURE_FEDERATED_TOKEN_FILE")) {
+        (Some(tenant), Some(client_id), Some(path)) => Some(Credential::WorkloadIdentity {
+            tenant: tenant.to_string(),
+            client_id: client_id.clone(),
+            token_file: PathBuf::from(path),
+        }),
+        _ => None,
+    };
+
+    let managed_identity = |probe: bool| Credential::ManagedIdentity {
+        endpoint: imds_endpoint.to_string(),
+        client_id: identity_client_id.map(|id| id.to_string()).or_else(|| client_id.clone()),
+        probe,
+    };
+
+    let cli = Credential::AzureCli { tenant: tenant.map(|t| t.to_string()) };
+
+    // IDENTITY_ENDPOINT / MSI_ENDPOINT mean App Service style hosts, which have no IMDS
+    let imds_expected = identity_client_id.is_some() || imds_endpoint != DEFAULT_IMDS_ENDPOINT;
+
+    match mode {
+        AuthMode::Auto => {
+            let (before_cli, after_cli) = if imds_expected {
+                (Some(managed_identity(false)), None)
+            } else {
+                (None, Some(managed_identity(true)))
+            };
+            Ok(client_secret.into_iter()
+                .chain(client_certificate)
+                .chain(workload_identity)
+                .chain(before_cli)
+                .chain(Some(cli))
+                .chain(after_cli)
+                .collect())
+        }
+        AuthMode::ClientSecret => client_secret
+            .map(|c| vec![c])
+            .ok_or_else(|| "Client secret auth needs AZURE_TENANT_ID (or --tenant), AZURE_CLIENT_ID and AZURE_CLIENT_SECRET".to_string()),
+        AuthMode::ClientCertificate => client_certificate
+            .map(|c| vec![c])
+            .ok_or_else(|| "Certificate auth needs AZURE_TENANT_ID (or --tenant), AZURE_CLIENT_ID and AZURE_CLIENT_CERTIFICATE_PATH".to_string()),
+        AuthMode::WorkloadIdentity => workload_identity
+            .map(|c| vec![c])
+            .ok_or_else(|| "Workload identity needs AZURE_TENANT_ID (or --tenant), AZURE_CLIENT_ID and AZURE_FEDERATED_TOKEN_FILE".to_string()),
+        AuthMode::ManagedIdentity => Ok(vec![managed_identity(false)]),
+        AuthMode::AzureCli => Ok(vec![cli]),
+    }
+}
+
+fn token_endpoint(authority: &str, tenant: &str) -> String {
+    format!("{}/{}/oauth2/v2.0/token", authority.trim_end_matches('/'), tenant)
+}
+
+/// parse an OAuth2 token endpoint response
+fn parse_oauth_token(token_data: &Value) -> Result<AccessToken, String> {
+    let token = token_data.get("access_token")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| "Token response has no access_token".to_string())?
+        .to_string();
+
+    // expires_in is a number from AAD but a string from some other endpoints
+    let as_seconds = |v: &Value| v.as_u64().or_else(|| v.as_str().and_then(|s| s.parse().ok()));
+    let expires_on = match token_data.get("expires_on").and_then(as_seconds) {
+        Some(epoch) => epoch,
+        None => unix_now() + token_data.get("expires_in")
+            .and_then(as_seconds)
+            .ok_or_else(|| "Token response has no expiry".to_string())?,
+    };
+
+    Ok(AccessToken { token, expires_on })
+}
+
+/// run the OAuth2 client-credentials flow against `{authority}/{tenant}/oauth2/v2.0/token`
+async fn request_client_credentials(
+    http: &reqwest::Client,
+    authority: &str,
+    tenant: &str,
+    client_id: &str,
+    audience: &str,
+    secret_params: &[(&str, &str)],
+) -> Result<AccessToken, String> {
+    let scope = format!("{}/.default", audience.trim_end_matches('/'));
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", client_id),
+        ("scope", scope.as_str()),
+    ];
+    form.extend_from_slice(secret_params);
+
+    let response = http
+        .post(token_endpoint(authority, tenant))
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("Token request failed: {}", e))?;
+
+    let status = response.status();
+    let body: Value = response.json().await.map_err(|_| "Failed to parse token".to_string())?;
+
+    if !status.is_success() {
+        let description = body.get("error_description")
+            .or_else(|| body.get("error"))
+            .and_then(|d| d.as_str())
+            .unwrap_or("no error description");
+        return Err(format!("Token request failed ({}): {}", status, description));
+    }
+
+    parse_oauth_token(&body)
+}
+
+/// request a managed identity token from `{endpoint}/metadata/identity/oauth2/token`
+async fn request_imds_token(
+    http: &reqwest::Client,
+    endpoint: &str,
+    client_id: Option<&str>,
+    audience: &str,
+    probe: bool,
+) -> Result<AccessToken, String> {
+    let mut query = vec![("api-version", "2018-02-01"), ("resource", audience)];
+    if let Some(client_id) = client_id {
+        query.push(("client_id", client_id));
+    }
+
+    // off Azure the link-local address never answers, so don't stall the chain on it
+    let timeout = if probe { Duration::from_secs(1) } else { Duration::from_secs(30) };
+
+    let response = http
+        .get(format!("{}/metadata/identity/oauth2/token", endpoint.trim_end_matches('/')))
+        .query(&query)
+        .header("Metadata", "true")
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| format!("IMDS request failed: {}", e))?;
+
+    let status = response.status();
+    let body: Value = response.json().await.map_err(|_| "Failed to parse token".to_string())?;
+
+    if !status.is_success() {
+        let description = body.get("error_description")
+            .or_else(|| body.get("error"))
+            .and_then(|d| d.as_str())
+            .unwrap_or("no error description");
+        return Err(format!("IMDS token request failed ({}): {}", status, description));
+    }
+
+    parse_oauth_token(&body)
+}
+
+/// sign a client assertion JWT with the private key from a PEM certificate file
+fn build_client_assertion(certificate_path: &Path, client_id: &str, audience: &str) -> Result<String, String> {
+    let contents = std::fs::read(certificate_path)
+        .map_err(|e| format!("Cannot read certificate '{}': {}", certificate_path.display(), e))?;
+    let blocks = pem::parse_many(&contents)
+        .map_err(|e| format!("Invalid PEM in '{}': {}", certificate_path.display(), e))?;
+
+    let certificate = blocks.iter()
+        .find(|b| b.tag() == "CERTIFICATE")
+        .ok_or_else(|| format!("No CERTIFICATE block in '{}'", certificate_path.display()))?;
+    let private_key = blocks.iter()
+        .find(|b| b.tag().ends_with("PRIVATE KEY"))
+        .ok_or_else(|| format!("No PRIVATE KEY block in '{}'", certificate_path.display()))?;
+
+    let key = jsonwebtoken::EncodingKey::from_rsa_pem(pem::encode(private_key).as_bytes())
+        .map_err(|e| format!("Unsupported private key in '{}': {}", certificate_path.display(), e))?;
+
+    // AAD identifies the certificate by the base64url SHA-1 thumbprint of its DER encoding
+    let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+    header.x5t = Some(URL_SAFE_NO_PAD.encode(Sha1::digest(certificate.contents())));
+
+    let now = unix_now();
+    let claims = serde_json::json!({
+        "aud": audience,
+        "iss": client_id,
+        "sub": client_id,
+        "jti": format!("{:x}-{:x}", now, std::process::id()),
+        "nbf": now,
+        "exp": now + 600,
+    });
+
+    jsonwebtoken::encode(&header, &claims, &key).map_err(|e| format!("Failed to sign client assertion: {}", e))
+}
+
+/// on-disk token cache: a json object of `"tenant|audience"` -> {accessToken, expiresOn}
+struct TokenCache {
+    path: PathBuf,
+}
+
+impl TokenCache {
+    fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("azure_search").join("tokens.json"))
+    }
+
+    /// tokens are kept per tenant, audience and credential chain, so one identity's
+    /// token is never handed to a run configured for another
+    fn key(tenant: Option<&str>, audience: &str, credentials: &[Credential]) -> String {
+        let chain: Vec<String> = credentials.iter().map(|c| c.cache_id()).collect();
+        format!("{}|{}|{}", tenant.unwrap_or("default"), audience, chain.join(","))
+    }
+
+    fn read_all(&self) -> serde_json::Map<String, Value> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+            .and_then(|v| v.as_object().cloned())
+            .unwrap_or_default()
+    }
+
+    fn load(&self, key: &str) -> Option<AccessToken> {
+        let entries = self.read_all();
+        let entry = entries.get(key)?;
+        Some(AccessToken {
+            token: entry.get("accessToken")?.as_str()?.to_string(),
+            expires_on: entry.get("expiresOn")?.as_u64()?,
+        })
+    }
+
+    fn store(&self, key: &str, token: &AccessToken) -> std::io::Result<()> {
+        let mut entries = self.read_all();
+        // drop anything that has already expired while we are rewriting the file
+        entries.retain(|_, entry| entry.get("expiresOn").and_then(|v| v.as_u64()).is_some_and(|exp| exp > unix_now()));
+        entries.insert(key.to_string(), serde_json::json!({
+            "accessToken": token.token,
+            "expiresOn": token.expires_on,
+        }));
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // write a fresh sibling file and rename it over the cache, so concurrent runs never
+        // read a partial file and the mode below applies even when the cache already exists
+        let temp_path = self.path.with_extension(format!("json.{}.tmp", std::process::id()));
+        let _ = std::fs::remove_file(&temp_path);
+        let mut file_options = std::fs::OpenOptions::new();
+        file_options.write(true).create_new(true);
+        // tokens are credentials - keep the cache readable by the owner only
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut file_options, 0o600);
+
+        let written = file_options.open(&temp_path)
+            .and_then(|mut file| file.write_all(serde_json::to_string(&Value::Object(entries)).unwrap().as_bytes()))
+            .and_then(|_| std::fs::rename(&temp_path, &self.path));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        written
+    }
+}
+
+/// how the shared request path retries throttling and transient failures
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RetryPolicy {
+    max_attempts: u32,
+    deadline: Duration,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            deadline: Duration::from_secs(120),
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// wait before the next attempt: the server's Retry-After when given, otherwise
+    /// exponential backoff with jitter so parallel callers don't retry in lockstep
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after;
+        }
+
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_delay);
+        let half = capped / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+/// connection failures, resets and timeouts; anything else (bad URL, redirect loop) won't get better
+fn is_retryable_transport(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request() || error.is_body()
+}
+
+/// read `Retry-After` (seconds or an HTTP date) or ARM's `x-ms-retry-after-ms`
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim().to_string());
+
+    if let Some(ms) = header("x-ms-retry-after-ms").and_then(|v| v.parse::<u64>().ok()) {
+        return Some(Duration::from_millis(ms));
+    }
+
+    let value = header("retry-after")?;
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = chrono::DateTime::parse_from_rfc2822(&value).ok()?.timestamp();
+    Some(Duration::from_secs(u64::try_from(at).ok()?.saturating_sub(unix_now())))
+}
+
+/// shared ARM client: one connection pool and one token per process
+struct ArmClient {
+    http: reqwest::Client,
+    credentials: Vec<Credential>,
+    arm_endpoint: String,
+    authority: String,
+    tenant: Option<String>,
+    audience: String,
+    token_cache: Option<TokenCache>,
+    token: Mutex<Option<AccessToken>>,
+    api_version_cache: Option<ApiVersionCache>,
+    /// provider api-versions already fetched by this client, keyed by lowercase namespace
+    api_versions: Mutex<HashMap<String, HashMap<String, String>>>,
+    retry: RetryPolicy,
+    verbose: bool,
+}
+
+impl ArmClient {
+    fn new(options: &Options) -> Result<Self, AzureSearchError> {
+        Ok(Self {
+            http: reqwest::Client::new(),
+            credentials: credential_chain(
+                options.auth_mode,
+                options.tenant.as_deref(),
+                &options.imds_endpoint,
+                options.identity_client_id.as_deref(),
+            ).map_err(|message| AzureSearchError::Auth { message })?,
+            arm_endpoint: options.cloud.arm_endpoint.clone(),
+            authority: options.cloud.authority.clone(),
+            tenant: options.tenant.clone(),
+            audience: options.cloud.audience.clone(),
+            token_cache: options.token_cache.clone().map(|path| TokenCache { path }),
+            token: Mutex::new(None),
+            api_version_cache: ApiVersionCache::open(),
+            api_versions: Mutex::new(HashMap::new()),
+            retry: options.retry,
+            verbose: options.verbose,
+        })
+    }
+
+    /// try each credential in order and return the first token obtained
+    async fn acquire_token(&self) -> Result<AccessToken, AzureSearchError> {
+        let mut failures = Vec::new();
+        for credential in &self.credentials {
+            match credential.get_token(&self.http, &self.authority, &self.audience).await {
+                Ok(token) => return Ok(token),
+                Err(e) => failures.push(format!("{}: {}", credential.name(), e)),
+            }
+        }
+        Err(AzureSearchError::Auth {
+            message: format!("no credential could provide a token ({})", failures.join("; ")),
+        })
+    }
+
+    /// return a token that is valid for at least TOKEN_REFRESH_MARGIN_SECS,
+    /// checking memory, then the disk cache, before running the credential chain
+    async fn token(&self) -> Result<String, AzureSearchError> {
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref().filter(|t| t.is_fresh()) {
+            return Ok(token.token.clone());
+        }
+
+        let key = TokenCache::key(self.tenant.as_deref(), &self.audience, &self.credentials);
+        if let Some(token) = self.token_cache.as_ref().and_then(|c| c.load(&key)).filter(|t| t.is_fresh()) {
+            let value = token.token.clone();
+            *cached = Some(token);
+            return Ok(value);
+        }
+
+        let token = self.acquire_token().await?;
+        if let Some(cache) = &self.token_cache {
+            if let Err(e) = cache.store(&key, &token) {
+                eprintln!("Warning: could not write token cache '{}': {}", cache.path.display(), e);
+            }
+        }
+
+        let value = token.token.clone();
+        *cached = Some(token);
+        Ok(value)
+    }
+
+    async fn get_json(&self, url: &str) -> Result<Value, AzureSearchError> {
+        self.execute(url, |http| http.get(url)).await
+    }
+
+    async fn post_json(&self, url: &str, body: &Value) -> Result<Value, AzureSearchError> {
+        self.execute(url, |http| http.post(url).json(body)).await
+    }
+
+    /// send a request built by `build`, retrying throttled (429), 5xx and
+    /// connection failures until the policy's attempts or deadline run out
+    async fn execute<F>(&self, url: &str, build: F) -> Result<Value, AzureSearchError>
+    where
+        F: Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    {
+        let started = Instant::now();
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let token = self.token().await?;
+            // bound each attempt by what is left of the deadline, so a stalled
+            // connection times out (and is retried) instead of hanging
+            let remaining = self.retry.deadline.saturating_sub(started.elapsed()).max(Duration::from_millis(1));
+            let result = build(&self.http).bearer_auth(&token).timeout(remaining).send().await;
+
+            let (reason, server_delay) = match &result {
+                Ok(response) => {
+                    if self.verbose {
+                        if let Some(remaining) = response.headers().get("x-ms-ratelimit-remaining-subscription-reads") {
+                            eprintln!("[verbose] {} {} (remaining subscription reads: {})",
+                                response.status().as_u16(), url, remaining.to_str().unwrap_or("?"));
+                        }
+                    }
+                    if !is_retryable_status(response.status()) {
+                        break read_arm_response(url, result.unwrap()).await;
+                    }
+                    (response.status().to_string(), retry_after(response.headers()))
+                }
+                Err(error) if is_retryable_transport(error) => (error.to_string(), None),
+                Err(_) => break Err(AzureSearchError::Transport { url: url.to_string(), source: result.unwrap_err() }),
+            };
+
+            let delay = self.retry.delay(attempt, server_delay);
+            if attempt >= self.retry.max_attempts || started.elapsed() + delay > self.retry.deadline {
+                break match result {
+                    Ok(response) => read_arm_response(url, response).await,
+                    Err(source) => Err(AzureSearchError::Transport { url: url.to_string(), source }),
+                };
+            }
+
+            if self.verbose {
+                eprintln!("[verbose] {} from {}; retrying in {:.1}s (attempt {}/{})",
+                    reason, url, delay.as_secs_f64(), attempt + 1, self.retry.max_attempts);
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn get_all_pages(&self, url: &str, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+        let mut items = Vec::new();
+        let mut pages = 0;
+        let mut truncated = false;
+        let mut next_url = Some(url.to_string());
+
+        while let Some(page_url) = next_url.take() {
+            if limit.max_pages.is_some_and(|max| pages >= max) {
+                truncated = true;
+                break;
+            }
+            if pages > 0 {
+                self.check_next_link(&page_url)?;
+            }
+
+            let mut page = self.get_json(&page_url).await?;
+            pages += 1;
+
+            if let Some(Value::Array(values)) = page.get_mut("value").map(Value::take) {
+                items.extend(values);
+            }
+
+            // an empty or missing nextLink means this was the last page
+            next_url = page.get("nextLink")
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string());
+
+            if let Some(max) = limit.max_items {
+                if items.len() >= max {
+                    truncated = items.len() > max || next_url.is_some();
+                    items.truncate(max);
+                    break;
+                }
+            }
+        }
+
+        Ok(PagedResult {
+            data: serde_json::json!({ "value": items }),
+            pages,
+            truncated,
+        })
+    }
+
+    /// the bearer token goes with every page, so a nextLink must stay on the ARM endpoint
+    fn check_next_link(&self, link: &str) -> Result<(), AzureSearchError> {
+        let origin = |url: &str| reqwest::Url::parse(url).ok()
+            .map(|u| (u.scheme().to_string(), u.host_str().map(|h| h.to_string()), u.port_or_known_default()));
+        match (origin(link), origin(&self.arm_endpoint)) {
+            (Some(link_origin), Some(arm_origin)) if link_origin == arm_origin => Ok(()),
+            _ => Err(AzureSearchError::usage(format!(
+                "ARM returned a nextLink outside {}: '{}'; not sending the token there",
+                self.arm_endpoint, link
+            ))),
+        }
+    }
+}
+
+/// turn an ARM response into json, keeping the ARM error body and correlation id on failure
+async fn read_arm_response(url: &str, response: reqwest::Response) -> Result<Value, AzureSearchError> {
+    let status = response.status();
+    let correlation_id = response.headers()
+        .get("x-ms-correlation-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    let body = response.bytes()
+        .await
+        .map_err(|source| AzureSearchError::Transport { url: url.to_string(), source })?;
+
+    if !status.is_success() {
+        return Err(AzureSearchError::Http {
+            status: status.as_u16(),
+            url: url.to_string(),
+            error: ArmError::parse(&body),
+            correlation_id,
+        });
+    }
+
+    serde_json::from_slice(&body).map_err(|source| AzureSearchError::Json { url: url.to_string(), source })
+}
+
+/// limits applied while following `nextLink` across result pages
+#[derive(Debug, Clone, Copy, Default)]
+struct PageLimit {
+    max_pages: Option<usize>,
+    max_items: Option<usize>,
+}
+
+/// list response with every fetched page merged into a single `value` array
+struct PagedResult {
+    data: Value,
+    pages: usize,
+    truncated: bool,
+}
+
+impl PagedResult {
+    fn report(&self) {
+        let count = self.data.get("value").and_then(|v| v.as_array()).map_or(0, |arr| arr.len());
+        eprintln!(
+            "Fetched {} page(s), {} item(s){}",
+            self.pages,
+            count,
+            if self.truncated { " (truncated by page/item limit)" } else { "" }
+        );
+    }
+}
+
+/// a subscription being queried, with its display name when it is known
+#[derive(Debug, Clone, PartialEq)]
+struct SubscriptionRef {
+    id: String,
+    display_name: Option<String>,
+}
+
+async fn list_subscription_pages(client: &ArmClient, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+    let url = format!("{}/subscriptions?api-version=2022-12-01", client.arm_endpoint);
+    client.get_all_pages(&url, limit).await
+}
+
+async fn list_subscriptions(client: &ArmClient) -> Result<Vec<Value>, AzureSearchError> {
+    let paged = list_subscription_pages(client, PageLimit::default()).await?;
+    Ok(paged.data.get("value").and_then(|v| v.as_array()).cloned().unwrap_or_default())
+}
+
+/// one row per subscription: display name, id, state and tenant
+fn subscription_rows(data: &Value) -> Value {
+    let rows: Vec<Value> = output_rows(data).iter()
+        .map(|sub| serde_json::json!({
+            "name": sub.get("displayName").cloned().unwrap_or(Value::Null),
+            "subscriptionId": sub.get("subscriptionId").cloned().unwrap_or(Value::Null),
+            "state": sub.get("state").cloned().unwrap_or(Value::Null),
+            "tenantId": sub.get("tenantId").cloned().unwrap_or(Value::Null),
+        }))
+        .collect();
+    serde_json::json!({ "value": rows })
+}
+
+/// whether `text` is a GUID such as a subscription id, as opposed to a display name
+fn is_guid(text: &str) -> bool {
+    text.len() == 36 && text.char_indices().all(|(i, c)| match i {
+        8 | 13 | 18 | 23 => c == '-',
+        _ => c.is_ascii_hexdigit(),
+    })
+}
+
+/// expand the subscription argument: one id or display name, a comma separated
+/// list of them, or `*` for every subscription the token can see
+///
+/// a single id is used as-is; anything else looks up the visible subscriptions,
+/// so names resolve to ids and results can be tagged with display names
+async fn resolve_subscriptions(client: &ArmClient, spec: &str) -> Result<Vec<SubscriptionRef>, AzureSearchError> {
+    let requested: Vec<&str> = spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+
+    if requested.is_empty() {
+        return Err(AzureSearchError::usage("Missing subscription"));
+    }
+    if requested.len() == 1 && is_guid(requested[0]) {
+        return Ok(vec![SubscriptionRef { id: requested[0].to_string(), display_name: None }]);
+    }
+
+    let visible: Vec<SubscriptionRef> = list_subscriptions(client).await?
+        .iter()
+        .filter_map(|sub| {
+            Some(SubscriptionRef {
+                id: sub.get("subscriptionId")?.as_str()?.to_string(),
+                display_name: sub.get("displayName").and_then(|n| n.as_str()).map(|n| n.to_string()),
+            })
+        })
+        .collect();
+
+    if requested.contains(&"*") {
+        if visible.is_empty() {
+            return Err(AzureSearchError::NotFound { message: "No subscriptions are visible to this token".to_string() });
+        }
+        return Ok(visible);
+    }
+
+    requested.iter()
+        .map(|wanted| {
+            if let Some(sub) = visible.iter().find(|sub| sub.id.eq_ignore_ascii_case(wanted)) {
+                return Ok(sub.clone());
+            }
+            let named: Vec<&SubscriptionRef> = visible.iter()
+                .filter(|sub| sub.display_name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(wanted)))
+                .collect();
+            match named.as_slice() {
+                [sub] => Ok((*sub).clone()),
+                // an id the listing does not show may still be usable, e.g. with a narrower role
+                [] if is_guid(wanted) => Ok(SubscriptionRef { id: wanted.to_string(), display_name: None }),
+                [] => Err(AzureSearchError::NotFound {
+                    message: format!("No visible subscription has the id or name '{}'; run 'subscriptions' to list them", wanted),
+                }),
+                _ => Err(AzureSearchError::usage(format!(
+                    "Several subscriptions are named '{}' ({}); use the id instead",
+                    wanted,
+                    named.iter().map(|sub| sub.id.as_str()).collect::<Vec<_>>().join(", ")
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// run `list` once per subscription, at most `concurrency` at a time, and merge
+/// the listings; with more than one subscription every resource is tagged with
+/// `subscriptionId` and `subscriptionDisplayName`
+async fn fan_out<F, Fut>(
+    subscriptions: &[SubscriptionRef],
+    concurrency: usize,
+    limit: PageLimit,
+    list: F,
+) -> Result<PagedResult, AzureSearchError>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<PagedResult, AzureSearchError>>,
+{
+    let results: Vec<PagedResult> = stream::iter(subscriptions.iter().map(|sub| list(sub.id.clone())))
+        .buffered(concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    Ok(merge_listings(subscriptions, results, limit))
+}
+
+fn merge_listings(subscriptions: &[SubscriptionRef], results: Vec<PagedResult>, limit: PageLimit) -> PagedResult {
+    let tag = subscriptions.len() > 1;
+    let mut items = Vec::new();
+    let mut pages = 0;
+    let mut truncated = false;
+
+    for (sub, mut paged) in subscriptions.iter().zip(results) {
+        pages += paged.pages;
+        truncated |= paged.truncated;
+
+        if let Some(Value::Array(values)) = paged.data.get_mut("value").map(Value::take) {
+            for mut resource in values {
+                if let (true, Some(map)) = (tag, resource.as_object_mut()) {
+                    map.entry("subscriptionId").or_insert_with(|| Value::String(sub.id.clone()));
+                    map.entry("subscriptionDisplayName").or_insert_with(|| {
+                        sub.display_name.clone().map(Value::String).unwrap_or(Value::Null)
+                    });
+                }
+                items.push(resource);
+            }
+        }
+    }
+
+    // per-subscription limits still leave up to N items each, so apply the item limit overall too
+    if let Some(max) = limit.max_items {
+        if items.len() > max {
+            items.truncate(max);
+            truncated = true;
+        }
+    }
+
+    PagedResult {
+        data: serde_json::json!({ "value": items }),
+        pages,
+        truncated,
+    }
+}
+
+/// what an Azure Resource Graph query runs over
+#[derive(Debug, Clone, PartialEq)]
+enum GraphScope {
+    Subscriptions(Vec<String>),
+    ManagementGroups(Vec<String>),
+}
+
+/// largest page Resource Graph will return
+const GRAPH_PAGE_SIZE: usize = 1000;
+
+/// run a KQL query against `Microsoft.ResourceGraph/resources`, following
+/// `$skipToken` the same way list calls follow `nextLink`
+async fn query_resource_graph(client: &ArmClient, query: &str, scope: &GraphScope, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+    let url = format!(
+        "{}/providers/Microsoft.ResourceGraph/resources?api-version=2022-10-01",
+        client.arm_endpoint
+    );
+
+    let mut request = serde_json::json!({
+        "query": query,
+        "options": {
+            "resultFormat": "objectArray",
+            "$top": limit.max_items.map_or(GRAPH_PAGE_SIZE, |max| max.min(GRAPH_PAGE_SIZE)),
+        }
+    });
+    match scope {
+        GraphScope::Subscriptions(ids) => request["subscriptions"] = serde_json::json!(ids),
+        GraphScope::ManagementGroups(ids) => request["managementGroups"] = serde_json::json!(ids),
+    }
+
+    let mut items = Vec::new();
+    let mut pages = 0;
+    let mut truncated = false;
+
+    loop {
+        if limit.max_pages.is_some_and(|max| pages >= max) {
+            truncated = true;
+            break;
+        }
+
+        let mut page = client.post_json(&url, &request).await?;
+        pages += 1;
+
+        if let Some(Value::Array(rows)) = page.get_mut("data").map(Value::take) {
+            items.extend(rows);
+        }
+
+        let skip_token = page.get("$skipToken")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string());
+
+        if let Some(max) = limit.max_items {
+            if items.len() >= max {
+                truncated = items.len() > max || skip_token.is_some();
+                items.truncate(max);
+                break;
+            }
+        }
+
+        match skip_token {
+            Some(token) => request["options"]["$skipToken"] = Value::String(token),
+            None => break,
+        }
+    }
+
+    Ok(PagedResult {
+        data: serde_json::json!({ "value": items }),
+        pages,
+        truncated,
+    })
+}
+
+/// GET one resource; `resource_name` is "parent/child" for child types
+async fn get_resource_json(client: &ArmClient, subscription: &str, rg: &str, resource_type: &ResolvedType, resource_name: &str) -> Result<Value, AzureSearchError> {
+    let names: Vec<&str> = resource_name.split('/').collect();
+    if names.len() != resource_type.types.len() {
+        return Err(AzureSearchError::usage(format!(
+            "{} needs {} name(s) separated by '/', got '{}'",
+            resource_type.full_name(),
+            resource_type.types.len(),
+            resource_name
+        )));
+    }
+
+    let url = format!(
+        "{}/subscriptions/{}/resourceGroups/{}/providers/{}?api-version={}",
+        client.arm_endpoint, subscription, rg, resource_type.path(&names)?, resource_type.api_version
+    );
+
+    client.get_json(&url).await
+}
+
+/// list the children of type `resource_type` under the parent named `parent_name`
+async fn list_child_resources(client: &ArmClient, subscription: &str, rg: &str, resource_type: &ResolvedType, parent_name: &str, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+    let names: Vec<&str> = parent_name.split('/').collect();
+    let url = format!(
+        "{}/subscriptions/{}/resourceGroups/{}/providers/{}?api-version={}",
+        client.arm_endpoint, subscription, rg, resource_type.path(&names)?, resource_type.api_version
+    );
+
+    client.get_all_pages(&url, limit).await
+}
+
+async fn list_all_resources(client: &ArmClient, subscription: &str, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+    let url = format!(
+        "{}/subscriptions/{}/resources?api-version=2021-04-01",
+        client.arm_endpoint, subscription
+    );
+
+    client.get_all_pages(&url, limit).await
+}
+
+fn require_top_level(resource_type: &ResolvedType) -> Result<(), AzureSearchError> {
+    if resource_type.is_child() {
+        return Err(AzureSearchError::usage(format!(
+            "{} is a child type; list it with list --rg <resource-group> --type {} --name <parent-name>",
+            resource_type.full_name(),
+            resource_type.full_name()
+        )));
+    }
+    Ok(())
+}
+
+async fn list_resources_by_type(client: &ArmClient, subscription: &str, resource_type: &ResolvedType, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+    require_top_level(resource_type)?;
+    let url = format!(
+        "{}/subscriptions/{}/providers/{}?api-version={}",
+        client.arm_endpoint, subscription, resource_type.full_name(), resource_type.api_version
+    );
+
+    client.get_all_pages(&url, limit).await
+}
+
+async fn list_all_resources_in_rg(client: &ArmClient, subscription: &str, rg: &str, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+    let url = format!(
+        "{}/subscriptions/{}/resourceGroups/{}/resources?api-version=2021-04-01",
+        client.arm_endpoint, subscription, rg
+    );
+
+    client.get_all_pages(&url, limit).await
+}
+
+async fn list_resource_groups(client: &ArmClient, subscription: &str, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+    let url = format!(
+        "{}/subscriptions/{}/resourcegroups?api-version=2021-04-01",
+        client.arm_endpoint, subscription
+    );
+
+    client.get_all_pages(&url, limit).await
+}
+
+async fn list_resources_in_rg(client: &ArmClient, subscription: &str, rg: &str, resource_type: &ResolvedType, limit: PageLimit) -> Result<PagedResult, AzureSearchError> {
+    require_top_level(resource_type)?;
+    let url = format!(
+        "{}/subscriptions/{}/resourceGroups/{}/providers/{}?api-version={}",
+        client.arm_endpoint, subscription, rg, resource_type.full_name(), resource_type.api_version
+    );
+
+    client.get_all_pages(&url, limit).await
+}
+
+/// GET a resource from its id, resolving the api-version for its type
+async fn get_resource_by_id(client: &ArmClient, aliases: &AliasTable, id: &str) -> Result<Value, AzureSearchError> {
+    let resource_id = ResourceId::parse(id)?;
+    let (Some(rg), Some(resource_type), Some(name)) = (&resource_id.resource_group, resource_id.resource_type(), resource_id.name()) else {
+        return Err(AzureSearchError::usage(format!("'{}' is not a resource in a resource group", id)));
+    };
+    let resource_type = resolve_resource_type(client, aliases, &resource_type).await?;
+    get_resource_json(client, &resource_id.subscription, rg, &resource_type, &name).await
+}
+
+/// how many hops --expand-refs follows when no depth is given
+const DEFAULT_EXPAND_DEPTH: usize = 1;
+
+/// whether `id` is another resource's id: not `own_id` itself or one of its children
+fn is_reference(id: &str, own_id: &str) -> bool {
+    let id = id.to_lowercase();
+    let own_id = own_id.to_lowercase();
+    if !own_id.is_empty() && (id == own_id || id.starts_with(&format!("{}/", own_id))) {
+        return false;
+    }
+    ResourceId::parse(&id).is_ok_and(|r| r.namespace.is_some())
+}
+
+/// collect the `id` fields inside `data` that point at other resources, once each
+fn collect_references(data: &Value, own_id: &str, found: &mut Vec<String>) {
+    match data {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::String(id) if key.eq_ignore_ascii_case("id") => {
+                        if is_reference(id, own_id) && !found.iter().any(|f| f.eq_ignore_ascii_case(id)) {
+                            found.push(id.clone());
+                        }
+                    }
+                    _ => collect_references(value, own_id, found),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_references(item, own_id, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// fetch what `data` refers to by id, and what those refer to, up to `depth` hops,
+/// and inline it all under `_resolved` keyed by id
+///
+/// each id is fetched once however often it appears, `concurrency` at a time;
+/// one that cannot be fetched (gone, or in a subscription we cannot read) is
+/// recorded as `{"error": ...}` instead of failing the whole command
+async fn expand_references<F, Fut>(data: &mut Value, depth: usize, concurrency: usize, fetch: F)
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<Value, AzureSearchError>>,
+{
+    let own_id = data.get("id").and_then(|id| id.as_str()).unwrap_or_default().to_string();
+    let mut resolved = serde_json::Map::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut failed = 0;
+    let mut frontier = Vec::new();
+    collect_references(data, &own_id, &mut frontier);
+
+    for _ in 0..depth {
+        // references back into the starting resource are already in the output
+        frontier.retain(|id| is_reference(id, &own_id) && seen.insert(id.to_lowercase()));
+        if frontier.is_empty() {
+            break;
+        }
+
+        let fetched: Vec<(String, Result<Value, AzureSearchError>)> = stream::iter(frontier.drain(..).map(|id| {
+            let request = fetch(id.clone());
+            async move { (id, request.await) }
+        }))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+
+        for (id, result) in fetched {
+            match result {
+                Ok(resource) => {
+                    collect_references(&resource, &id, &mut frontier);
+                    resolved.insert(id, resource);
+                }
+                Err(e) => {
+                    failed += 1;
+                    resolved.insert(id, serde_json::json!({ "error": e.to_string() }));
+                }
+            }
+        }
+    }
+
+    eprintln!(
+        "Resolved {} reference(s){}",
+        resolved.len() - failed,
+        if failed > 0 { format!(", {} could not be fetched", failed) } else { String::new() }
+    );
+    if let Some(map) = data.as_object_mut() {
+        map.insert("_resolved".to_string(), Value::Object(resolved));
+    }
+}
+
+/// a resource in a dependency graph; external ones are referenced from the
+/// listed resources but lie outside the group or subscription that was listed
+#[derive(Debug, Clone, PartialEq)]
+struct GraphNode {
+    id: String,
+    name: String,
+    resource_type: String,
+    external: bool,
+}
+
+/// resources and the id references between them, as (from, to) node indexes
+#[derive(Debug, Clone, PartialEq)]
+struct ResourceGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<(usize, usize)>,
+    /// node index by lowercase id
+    index: HashMap<String, usize>,
+}
+
+impl ResourceGraph {
+    /// link every resource to the resources its `id` fields point at; a reference
+    /// to a child such as a subnet or backend pool counts as one to its top-level
+    /// resource, and references within a resource are not edges
+    fn build(resources: &[Value]) -> Self {
+        let node_for = |id: &str, external: bool| -> Option<GraphNode> {
+            let parsed = ResourceId::parse(id).ok()?;
+            let namespace = parsed.namespace.as_ref()?;
+            Some(GraphNode {
+                id: if external { top_level_id(&parsed) } else { id.to_string() },
+                name: parsed.names.first()?.clone(),
+                resource_type: format!("{}/{}", namespace, parsed.types.first()?),
+                external,
+            })
+        };
+
+        let mut graph = ResourceGraph { nodes: Vec::new(), edges: Vec::new(), index: HashMap::new() };
+        let listed: Vec<(&Value, usize)> = resources.iter()
+            .filter_map(|r| {
+                let id = r.get("id")?.as_str()?;
+                Some((r, graph.add(node_for(id, false)?)))
+            })
+            .collect();
+
+        let mut edges = HashSet::new();
+        for (resource, from) in listed {
+            let mut references = Vec::new();
+            collect_references(resource, &graph.nodes[from].id, &mut references);
+            for reference in references {
+                let to = match graph.owner_of(&reference) {
+                    Some(to) => to,
+                    None => match node_for(&reference, true) {
+                        Some(node) => graph.add(node),
+                        None => continue,
+                    },
+                };
+                if to != from {
+                    edges.insert((from, to));
+                }
+            }
+        }
+        graph.edges = edges.into_iter().collect();
+        graph.edges.sort();
+        graph
+    }
+
+    /// add `node` unless a node with its id is already there, and return its index
+    fn add(&mut self, node: GraphNode) -> usize {
+        let next = self.nodes.len();
+        let index = *self.index.entry(node.id.to_lowercase()).or_insert(next);
+        if index == next {
+            self.nodes.push(node);
+        }
+        index
+    }
+
+    fn find(&self, id: &str) -> Option<usize> {
+        self.index.get(&id.to_lowercase()).copied()
+    }
+
+    /// the node `id` belongs to: the node itself, or the nearest parent id that is a node
+    fn owner_of(&self, id: &str) -> Option<usize> {
+        let mut id = id.to_lowercase();
+        loop {
+            if let Some(&index) = self.index.get(&id) {
+                return Some(index);
+            }
+            // drop the last "/type/name" pair
+            let parent = id.rsplitn(3, '/').nth(2)?.len();
+            id.truncate(parent);
+        }
+    }
+
+    /// the indexes each node's references point at
+    fn successors(&self) -> Vec<Vec<usize>> {
+        let mut successors = vec![Vec::new(); self.nodes.len()];
+        for &(from, to) in &self.edges {
+            successors[from].push(to);
+        }
+        successors
+    }
+
+    /// the part of the graph reachable from `start` along its references
+    fn reachable_from(&self, start: usize) -> Self {
+        let successors = self.successors();
+        let mut keep = vec![false; self.nodes.len()];
+        let mut queue = vec![start];
+        keep[start] = true;
+        while let Some(node) = queue.pop() {
+            for &to in &successors[node] {
+                if !keep[to] {
+                    keep[to] = true;
+                    queue.push(to);
+                }
+            }
+        }
+
+        let mut graph = ResourceGraph { nodes: Vec::new(), edges: Vec::new(), index: HashMap::new() };
+        let mut renumbered = vec![None; self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate().filter(|(i, _)| keep[*i]) {
+            renumbered[index] = Some(graph.add(node.clone()));
+        }
+        graph.edges = self.edges.iter()
+            .filter_map(|&(from, to)| Some((renumbered[from]?, renumbered[to]?)))
+            .collect();
+        graph
+    }
+
+    /// the resource a --name or id picks: an exact id, else a unique name, optionally of one type
+    fn select(&self, name: &str, resource_type: Option<&ResolvedType>) -> Result<usize, AzureSearchError> {
+        if let Some(index) = self.find(name) {
+            return Ok(index);
+        }
+        let candidates: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| !self.nodes[i].external && self.nodes[i].name.eq_ignore_ascii_case(name))
+            .filter(|&i| resource_type.is_none_or(|t| t.full_name().eq_ignore_ascii_case(&self.nodes[i].resource_type)))
+            .collect();
+        match candidates.as_slice() {
+            [index] => Ok(*index),
+            [] => Err(AzureSearchError::NotFound { message: format!("No listed resource is named '{}'", name) }),
+            _ => Err(AzureSearchError::usage(format!(
+                "Several resources are named '{}'; add --type or give the id: {}",
+                name,
+                candidates.iter().map(|&i| self.nodes[i].id.as_str()).collect::<Vec<_>>().join(", ")
+            ))),
+        }
+    }
+
+    fn label(node: &GraphNode) -> String {
+        let short_type = node.resource_type.rsplit('/').next().unwrap_or_default();
+        format!("{}\n{}", node.name, short_type)
+    }
+
+    fn to_dot(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"));
+        let mut dot = String::from("digraph resources {\n  rankdir=LR;\n  node [shape=box];\n");
+        for node in &self.nodes {
+            let style = if node.external { ", style=dashed" } else { "" };
+            dot.push_str(&format!("  {} [label={}{}];\n", quote(&node.id), quote(&Self::label(node)), style));
+        }
+        for &(from, to) in &self.edges {
+            dot.push_str(&format!("  {} -> {};\n", quote(&self.nodes[from].id), quote(&self.nodes[to].id)));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("graph LR\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let label = Self::label(node).replace('"', "#quot;").replace('\n', "<br/>");
+            let (open, close) = if node.external { ("([", "])") } else { ("[", "]") };
+            mermaid.push_str(&format!("  n{}{}\"{}\"{}\n", index, open, label, close));
+        }
+        for &(from, to) in &self.edges {
+            mermaid.push_str(&format!("  n{} --> n{}\n", from, to));
+        }
+        mermaid
+    }
+
+    /// `{"nodes": [...], "adjacency": {"<id>": ["<referenced id>", ...]}}`
+    fn to_json(&self) -> Value {
+        let nodes: Vec<Value> = self.nodes.iter()
+            .map(|n| serde_json::json!({ "id": n.id, "name": n.name, "type": n.resource_type, "external": n.external }))
+            .collect();
+        let mut adjacency = serde_json::Map::new();
+        for (node, targets) in self.nodes.iter().zip(self.successors()) {
+            let targets: Vec<Value> = targets.into_iter().map(|to| Value::String(self.nodes[to].id.clone())).collect();
+            adjacency.insert(node.id.clone(), Value::Array(targets));
+        }
+        serde_json::json!({ "nodes": nodes, "adjacency": adjacency })
+    }
+}
+
+/// the id of the top-level resource an id belongs to, e.g. the VNet of a subnet
+fn top_level_id(id: &ResourceId) -> String {
+    ResourceId { types: id.types[..1].to_vec(), names: id.names[..1].to_vec(), ..id.clone() }.to_string()
+}
+
+/// how `graph` writes its result
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum GraphFormat {
+    #[default]
+    Dot,
+    Mermaid,
+    Json,
+}
+
+impl GraphFormat {
+    fn parse(format: &str) -> Result<Self, AzureSearchError> {
+        match format.to_lowercase().as_str() {
+            "dot" | "graphviz" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            "json" => Ok(GraphFormat::Json),
+            other => Err(AzureSearchError::usage(format!("Unknown graph format '{}', expected dot, mermaid or json", other))),
+        }
+    }
+}
+
+/// one step of a field path such as `properties.subnets[0].name`
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    /// negative indexes count from the end
+    Index(i64),
+    Wildcard,
+}
+
+/// parse dotted and bracketed paths: `a.b`, `a[0]`, `a[-1]`, `a[*]`, `a.*.b`, `a["key.with.dots"]`
+fn parse_field_path(path: &str) -> Result<Vec<PathSegment>, AzureSearchError> {
+    let invalid = |reason: &str| AzureSearchError::usage(format!("Invalid field path '{}': {}", path, reason));
+    let mut segments = Vec::new();
+    let mut pending = String::new();
+    let mut after_bracket = false;
+    let mut chars = path.chars().peekable();
+
+    let flush = |pending: &mut String, segments: &mut Vec<PathSegment>| {
+        if !pending.is_empty() {
+            let key = std::mem::take(pending);
+            segments.push(if key == "*" { PathSegment::Wildcard } else { PathSegment::Key(key) });
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                if pending.is_empty() && !after_bracket {
+                    return Err(invalid("empty segment"));
+                }
+                flush(&mut pending, &mut segments);
+                after_bracket = false;
+            }
+            '[' => {
+                flush(&mut pending, &mut segments);
+                let mut inner = String::new();
+                let quote = chars.next_if(|c| *c == '"' || *c == '\'');
+                loop {
+                    match chars.next() {
+                        Some(c) if Some(c) == quote => {
+                            if chars.next() != Some(']') {
+                                return Err(invalid("expected ']' after quoted key"));
+                            }
+                            break;
+                        }
+                        Some(']') if quote.is_none() => break,
+                        Some(c) => inner.push(c),
+                        None => return Err(invalid("unclosed '['")),
+                    }
+                }
+                let segment = if quote.is_some() {
+                    PathSegment::Key(inner)
+                } else if inner.trim() == "*" {
+                    PathSegment::Wildcard
+                } else {
+                    PathSegment::Index(inner.trim().parse().map_err(|_| invalid("index must be a number, '*' or a quoted key"))?)
+                };
+                segments.push(segment);
+                after_bracket = true;
+            }
+            _ if after_bracket => return Err(invalid("expected '.' or '[' after ']'")),
+            _ => pending.push(c),
+        }
+    }
+
+    if pending.is_empty() && !after_bracket {
+        return Err(invalid("empty segment"));
+    }
+    flush(&mut pending, &mut segments);
+    Ok(segments)
+}
+
+/// every value a parsed path reaches; keys match exactly, else case-insensitively
+fn select_path<'a>(data: &'a Value, segments: &[PathSegment]) -> Vec<&'a Value> {
+    let mut current = vec![data];
+    for segment in segments {
+        current = current.into_iter().flat_map(|value| -> Vec<&'a Value> {
+            match (segment, value) {
+                (PathSegment::Key(key), Value::Object(map)) => map.get(key)
+                    .or_else(|| map.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v))
+                    .into_iter()
+                    .collect(),
+                (PathSegment::Index(index), Value::Array(items)) => {
+                    let index = if *index < 0 { items.len() as i64 + index } else { *index };
+                    usize::try_from(index).ok().and_then(|i| items.get(i)).into_iter().collect()
+                }
+                (PathSegment::Wildcard, Value::Object(map)) => map.values().collect(),
+                (PathSegment::Wildcard, Value::Array(items)) => items.iter().collect(),
+                _ => Vec::new(),
+            }
+        }).collect();
+    }
+    current
+}
+
+/// a top-level key, or a nested path; paths with `*` return an array of every match
+fn get_field(data: &Value, field: &str) -> Option<Value> {
+    if let Some(value) = data.get(field) {
+        return Some(value.clone());
+    }
+
+    let segments = parse_field_path(field).ok()?;
+    let matches = select_path(data, &segments);
+    if segments.contains(&PathSegment::Wildcard) {
+        (!matches.is_empty()).then(|| Value::Array(matches.into_iter().cloned().collect()))
+    } else {
+        matches.first().map(|v| (*v).clone())
+    }
+}
+
+/// `{"name": ..., "<path>": ...}` for every resource in a listing that has the field
+fn project_field(data: &Value, path: &str) -> Vec<Value> {
+    output_rows(data).iter()
+        .filter_map(|resource| {
+            let value = get_field(resource, path)?;
+            let mut row = serde_json::Map::new();
+            row.insert("name".to_string(), resource.get("name").cloned().unwrap_or(Value::Null));
+            row.insert(path.to_string(), value);
+            Some(Value::Object(row))
+        })
+        .collect()
+}
+
+/// which side of each key/value pair a search looks at (--match)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum MatchTarget {
+    #[default]
+    Both,
+    Keys,
+    Values,
+}
+
+impl MatchTarget {
+    fn parse(target: &str) -> Result<Self, AzureSearchError> {
+        match target.to_lowercase().as_str() {
+            "both" | "all" => Ok(MatchTarget::Both),
+            "keys" | "key" => Ok(MatchTarget::Keys),
+            "values" | "value" => Ok(MatchTarget::Values),
+            other => Err(AzureSearchError::usage(format!("Unknown --match '{}', expected keys, values or both", other))),
+        }
+    }
+}
+
+/// how a search term is compared with keys and string values
+#[derive(Debug, Clone)]
+enum Pattern {
+    /// case-insensitive substring, for `search:` and `subsearch:`
+    Substring(String),
+    /// case-insensitive whole value, for `exact:`
+    Exact(String),
+    /// case-insensitive unless the pattern says `(?-i)`, for `regex:` and `subregex:`
+    Regex(regex::Regex),
+    /// numeric comparison such as `port>=1024`; the key must contain `key`, if one is given
+    Compare { key: String, op: CompareOp, number: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
+
+impl CompareOp {
+    fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            CompareOp::Less => left < right,
+            CompareOp::LessOrEqual => left <= right,
+            CompareOp::Greater => left > right,
+            CompareOp::GreaterOrEqual => left >= right,
+            CompareOp::Equal => left == right,
+        }
+    }
+}
+
+/// split `port>=1024` into key, operator and number; None when the term is not a comparison
+///
+/// the key is matched against one field name, so a dotted path is an error rather
+/// than a comparison that silently never matches
+fn parse_comparison(term: &str) -> Result<Option<(String, CompareOp, f64)>, &'static str> {
+    let Some(position) = term.find(['<', '>', '=']) else { return Ok(None) };
+    let (key, rest) = term.split_at(position);
+    let (op, number) = [
+        (">=", CompareOp::GreaterOrEqual),
+        ("<=", CompareOp::LessOrEqual),
+        ("==", CompareOp::Equal),
+        (">", CompareOp::Greater),
+        ("<", CompareOp::Less),
+        ("=", CompareOp::Equal),
+    ].iter().find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|number| (*op, number))).unzip();
+
+    let key = key.trim();
+    let number = number.and_then(|n| n.trim().parse::<f64>().ok()).filter(|n| n.is_finite());
+    let (Some(op), Some(number)) = (op, number) else { return Ok(None) };
+    if !key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.') {
+        return Ok(None);
+    }
+    if key.contains('.') {
+        return Err("a comparison key is one field name such as port, not a path");
+    }
+    Ok(Some((key.to_lowercase(), op, number)))
+}
+
+/// numbers and numeric strings such as "443" as f64
+fn numeric_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok().filter(|n: &f64| n.is_finite()),
+        _ => None,
+    }
+}
+
+/// the text a scalar is matched as; objects, arrays and null have none
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Matcher {
+    term: String,
+    pattern: Pattern,
+    target: MatchTarget,
+}
+
+impl Matcher {
+    fn substring(term: &str) -> Self {
+        Self { term: term.to_string(), pattern: Pattern::Substring(term.to_lowercase()), target: MatchTarget::Both }
+    }
+
+    /// the `search:`/`subsearch:` matcher: a numeric comparison when the term is one, else a substring
+    fn search(term: &str) -> Result<Self, &'static str> {
+        Ok(match parse_comparison(term)? {
+            Some((key, op, number)) => Self { term: term.to_string(), pattern: Pattern::Compare { key, op, number }, target: MatchTarget::Both },
+            None => Self::substring(term),
+        })
+    }
+
+    fn exact(term: &str) -> Self {
+        Self { term: term.to_string(), pattern: Pattern::Exact(term.to_lowercase()), target: MatchTarget::Both }
+    }
+
+    fn regex(term: &str) -> Result<Self, AzureSearchError> {
+        let regex = regex::RegexBuilder::new(term)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| AzureSearchError::usage(format!("Invalid regex '{}': {}", term, e)))?;
+        Ok(Self { term: term.to_string(), pattern: Pattern::Regex(regex), target: MatchTarget::Both })
+    }
+
+    fn with_target(mut self, target: MatchTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    fn text_matches(&self, text: &str) -> bool {
+        match &self.pattern {
+            Pattern::Substring(term) => text.to_lowercase().contains(term.as_str()),
+            Pattern::Exact(term) => text.to_lowercase() == *term,
+            Pattern::Regex(regex) => regex.is_match(text),
+            Pattern::Compare { .. } => false,
+        }
+    }
+
+    fn key_matches(&self, key: &str) -> bool {
+        self.target != MatchTarget::Values && self.text_matches(key)
+    }
+
+    fn value_matches(&self, value: &Value) -> bool {
+        self.target != MatchTarget::Keys && scalar_text(value).is_some_and(|text| self.text_matches(&text))
+    }
+
+    /// an object entry: its key or scalar value matches, or it satisfies the comparison
+    fn entry_matches(&self, key: &str, value: &Value) -> bool {
+        match &self.pattern {
+            Pattern::Compare { .. } => self.compare_matches(Some(key), value),
+            _ => self.key_matches(key) || self.value_matches(value),
+        }
+    }
+
+    /// an array element, compared under the key of the array that holds it
+    fn element_matches(&self, parent_key: Option<&str>, value: &Value) -> bool {
+        match &self.pattern {
+            Pattern::Compare { .. } => self.compare_matches(parent_key, value),
+            _ => self.value_matches(value),
+        }
+    }
+
+    fn compare_matches(&self, key: Option<&str>, value: &Value) -> bool {
+        let Pattern::Compare { key: wanted, op, number } = &self.pattern else {
+            return false;
+        };
+        let key_ok = wanted.is_empty() || key.is_some_and(|k| k.to_lowercase().contains(wanted.as_str()));
+        key_ok && numeric_value(value).is_some_and(|n| op.holds(n, *number))
+    }
+}
+
+/// a boolean search over whole resources: terms joined with AND, OR, NOT and parentheses
+#[derive(Debug, Clone)]
+enum SearchExpr {
+    Term(Matcher),
+    And(Box<SearchExpr>, Box<SearchExpr>),
+    Or(Box<SearchExpr>, Box<SearchExpr>),
+    Not(Box<SearchExpr>),
+}
+
+impl From<Matcher> for SearchExpr {
+    fn from(matcher: Matcher) -> Self {
+        SearchExpr::Term(matcher)
+    }
+}
+
+impl SearchExpr {
+    /// the expression as an operand of AND, OR or NOT, parenthesized unless it is a term or a NOT
+    fn operand(&self) -> String {
+        match self {
+            SearchExpr::And(..) | SearchExpr::Or(..) => format!("({})", self),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SearchExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchExpr::Term(matcher) if matcher.term.contains(' ') => write!(f, "\"{}\"", matcher.term),
+            SearchExpr::Term(matcher) => write!(f, "{}", matcher.term),
+            SearchExpr::And(left, right) => write!(f, "{} AND {}", left.operand(), right.operand()),
+            SearchExpr::Or(left, right) => write!(f, "{} OR {}", left.operand(), right.operand()),
+            SearchExpr::Not(inner) => write!(f, "NOT {}", inner.operand()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SearchToken {
+    Word(String),
+    Quoted(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl SearchExpr {
+    /// parse a `search:`/`subsearch:` term; terms without operators, parentheses or
+    /// quotes stay a single matcher, so `search:foo bar` still means the phrase
+    fn parse(term: &str, target: MatchTarget) -> Result<Self, AzureSearchError> {
+        let invalid = |reason: &str| AzureSearchError::usage(format!("Invalid search expression '{}': {}", term, reason));
+        // parentheses only group when they stand alone or next to AND/OR/NOT, so
+        // terms like `count()` or `foo(bar)` stay literal as they always were
+        let literal = tokenize_search(term, false).map_err(invalid)?;
+        let grouping = literal.iter().any(|t| match t {
+            SearchToken::Word(word) => word.chars().all(|c| c == '(' || c == ')'),
+            SearchToken::Quoted(_) => false,
+            _ => true,
+        });
+        let tokens = if grouping { tokenize_search(term, true).map_err(invalid)? } else { literal };
+        if tokens.iter().all(|t| matches!(t, SearchToken::Word(_))) {
+            return Ok(SearchExpr::Term(Matcher::search(term).map_err(invalid)?.with_target(target)));
+        }
+
+        let mut position = 0;
+        let expr = parse_or(&tokens, &mut position, target).map_err(invalid)?;
+        if position < tokens.len() {
+            return Err(invalid("unexpected ')'"));
+        }
+        Ok(expr)
+    }
+
+    fn matches(&self, resource: &Value) -> bool {
+        match self {
+            SearchExpr::Term(matcher) => resource_contains_term(resource, matcher),
+            SearchExpr::And(left, right) => left.matches(resource) && right.matches(resource),
+            SearchExpr::Or(left, right) => left.matches(resource) || right.matches(resource),
+            SearchExpr::Not(inner) => !inner.matches(resource),
+        }
+    }
+
+    /// the terms whose matches are worth reporting, i.e. those not under a NOT
+    fn positive_terms(&self) -> Vec<&Matcher> {
+        match self {
+            SearchExpr::Term(matcher) => vec![matcher],
+            SearchExpr::And(left, right) | SearchExpr::Or(left, right) => {
+                let mut terms = left.positive_terms();
+                terms.extend(right.positive_terms());
+                terms
+            }
+            SearchExpr::Not(_) => Vec::new(),
+        }
+    }
+}
+
+/// split a search term into words, quoted phrases and operators; with `grouping`
+/// off, parentheses are ordinary characters inside words
+fn tokenize_search(term: &str, grouping: bool) -> Result<Vec<SearchToken>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut chars = term.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' if grouping => {
+                chars.next();
+                tokens.push(if c == '(' { SearchToken::Open } else { SearchToken::Close });
+            }
+            '"' => {
+                chars.next();
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => phrase.push(c),
+                        None => return Err("unclosed quote"),
+                    }
+                }
+                tokens.push(SearchToken::Quoted(phrase));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '"' || (grouping && (c == '(' || c == ')')) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => SearchToken::And,
+                    "OR" => SearchToken::Or,
+                    "NOT" => SearchToken::Not,
+                    _ => SearchToken::Word(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_or(tokens: &[SearchToken], position: &mut usize, target: MatchTarget) -> Result<SearchExpr, &'static str> {
+    let mut expr = parse_and(tokens, position, target)?;
+    while tokens.get(*position) == Some(&SearchToken::Or) {
+        *position += 1;
+        expr = SearchExpr::Or(Box::new(expr), Box::new(parse_and(tokens, position, target)?));
+    }
+    Ok(expr)
+}
+
+/// AND binds tighter than OR; an operand followed directly by a quoted term, NOT
+/// or `(` is an implicit AND, while bare words side by side form one phrase (see parse_unary)
+fn parse_and(tokens: &[SearchToken], position: &mut usize, target: MatchTarget) -> Result<SearchExpr, &'static str> {
+    let mut expr = parse_unary(tokens, position, target)?;
+    loop {
+        match tokens.get(*position) {
+            Some(SearchToken::And) => *position += 1,
+            Some(SearchToken::Word(_) | SearchToken::Quoted(_) | SearchToken::Not | SearchToken::Open) => {}
+            _ => return Ok(expr),
+        }
+        expr = SearchExpr::And(Box::new(expr), Box::new(parse_unary(tokens, position, target)?));
+    }
+}
+
+fn parse_unary(tokens: &[SearchToken], position: &mut usize, target: MatchTarget) -> Result<SearchExpr, &'static str> {
+    let token = tokens.get(*position).ok_or("expected a term")?;
+    *position += 1;
+    match token {
+        SearchToken::Not => Ok(SearchExpr::Not(Box::new(parse_unary(tokens, position, target)?))),
+        SearchToken::Open => {
+            let expr = parse_or(tokens, position, target)?;
+            if tokens.get(*position) != Some(&SearchToken::Close) {
+                return Err("missing ')'");
+            }
+            *position += 1;
+            Ok(expr)
+        }
+        SearchToken::Quoted(phrase) => Ok(SearchExpr::Term(Matcher::substring(phrase).with_target(target))),
+        SearchToken::Word(word) => {
+            // neighbouring bare words form one phrase
+            let mut phrase = word.clone();
+            while let Some(SearchToken::Word(next)) = tokens.get(*position) {
+                phrase.push(' ');
+                phrase.push_str(next);
+                *position += 1;
+            }
+            Ok(SearchExpr::Term(Matcher::search(&phrase)?.with_target(target)))
+        }
+        SearchToken::And | SearchToken::Or => Err("AND/OR needs a term on both sides"),
+        SearchToken::Close => Err("unexpected ')'"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PredicateOp {
+    Equal,
+    NotEqual,
+    Contains,
+    NotContains,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Exists,
+    Missing,
+    /// address or prefix inside any of these networks
+    InCidr(Vec<(IpAddr, u8)>),
+}
+
+/// a `where:` clause comparing the value at a field path, e.g. `sku.name~Standard`
+#[derive(Debug, Clone)]
+struct Predicate {
+    text: String,
+    path: Vec<PathSegment>,
+    op: PredicateOp,
+    value: String,
+}
+
+impl Predicate {
+    fn parse(text: &str) -> Result<Self, AzureSearchError> {
+        let invalid = |reason: &str| AzureSearchError::usage(format!("Invalid where: clause '{}': {}", text, reason));
+        let trimmed = text.trim();
+
+        let (path, op, value) = if let Some(path) = trimmed.strip_suffix(" !exists") {
+            (path, PredicateOp::Missing, "")
+        } else if let Some(path) = trimmed.strip_suffix(" exists") {
+            (path, PredicateOp::Exists, "")
+        } else {
+            let (position, symbol) = find_predicate_operator(trimmed).ok_or_else(|| invalid("expected =, !=, ~, !~, <, <=, >, >=, exists or in"))?;
+            let value = trimmed[position + symbol.len()..].trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            let op = match symbol {
+                "=" | "==" => PredicateOp::Equal,
+                "!=" => PredicateOp::NotEqual,
+                "~" => PredicateOp::Contains,
+                "!~" => PredicateOp::NotContains,
+                "<" => PredicateOp::Less,
+                "<=" => PredicateOp::LessOrEqual,
+                ">" => PredicateOp::Greater,
+                ">=" => PredicateOp::GreaterOrEqual,
+                _ => {
+                    let networks = value.split(',')
+                        .map(|network| parse_cidr(network.trim()))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| invalid("'in' needs CIDR ranges such as 10.0.0.0/16"))?;
+                    PredicateOp::InCidr(networks)
+                }
+            };
+            (&trimmed[..position], op, value)
+        };
+
+        Ok(Self {
+            text: text.to_string(),
+            path: parse_field_path(path.trim())?,
+            op,
+            value: value.to_string(),
+        })
+    }
+
+    fn matches(&self, resource: &Value) -> bool {
+        let values: Vec<&Value> = select_path(resource, &self.path).into_iter().filter(|v| !v.is_null()).collect();
+        let any = |test: &dyn Fn(&Value) -> bool| values.iter().any(|v| test(v));
+        let ordering = |v: &Value| compare_field(v, &self.value);
+        let contains = |v: &Value| scalar_text(v).is_some_and(|t| t.to_lowercase().contains(&self.value.to_lowercase()));
+
+        // negations need the field: a listing without `properties` should not pass
+        // `properties.provisioningState!=Succeeded` for every resource
+        match &self.op {
+            PredicateOp::Equal => any(&|v| ordering(v) == Some(std::cmp::Ordering::Equal)),
+            PredicateOp::NotEqual => !values.is_empty() && !any(&|v| ordering(v) == Some(std::cmp::Ordering::Equal)),
+            PredicateOp::Contains => any(&contains),
+            PredicateOp::NotContains => !values.is_empty() && !any(&contains),
+            PredicateOp::Less => any(&|v| ordering(v).is_some_and(|o| o.is_lt())),
+            PredicateOp::LessOrEqual => any(&|v| ordering(v).is_some_and(|o| o.is_le())),
+            PredicateOp::Greater => any(&|v| ordering(v).is_some_and(|o| o.is_gt())),
+            PredicateOp::GreaterOrEqual => any(&|v| ordering(v).is_some_and(|o| o.is_ge())),
+            PredicateOp::Exists => !values.is_empty(),
+            PredicateOp::Missing => values.is_empty(),
+            PredicateOp::InCidr(networks) => any(&|v| {
+                scalar_text(v)
+                    .and_then(|t| parse_cidr(&t))
+                    .is_some_and(|address| networks.iter().any(|network| cidr_contains(*network, address)))
+            }),
+        }
+    }
+}
+
+/// the first comparison operator outside brackets and quotes, longest symbol first
+fn find_predicate_operator(text: &str) -> Option<(usize, &'static str)> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (position, c) in text.char_indices() {
+        match c {
+            '"' | '\'' if quote == Some(c) => quote = None,
+            '"' | '\'' if quote.is_none() && depth > 0 => quote = Some(c),
+            '[' if quote.is_none() => depth += 1,
+            ']' if quote.is_none() => depth -= 1,
+            _ if depth > 0 || quote.is_some() => {}
+            _ => {
+                let rest = &text[position..];
+                // compared through get() so a multi-byte character in a path cannot split
+                if rest.len() > 4 && rest.get(..4).is_some_and(|s| s.eq_ignore_ascii_case(" in ")) {
+                    return Some((position, " in "));
+                }
+                if let Some(symbol) = ["!=", "!~", "<=", ">=", "==", "=", "~", "<", ">"].into_iter().find(|s| rest.starts_with(s)) {
+                    return Some((position, symbol));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// numbers compare numerically, everything else as case-insensitive text
+fn compare_field(value: &Value, wanted: &str) -> Option<std::cmp::Ordering> {
+    if let (Some(left), Ok(right)) = (numeric_value(value), wanted.trim().parse::<f64>()) {
+        return left.partial_cmp(&right);
+    }
+    scalar_text(value).map(|text| text.to_lowercase().cmp(&wanted.to_lowercase()))
+}
+
+/// "10.0.0.0/16" or a bare address, which is a full-length prefix
+fn parse_cidr(text: &str) -> Option<(IpAddr, u8)> {
+    let (address, length) = match text.split_once('/') {
+        Some((address, length)) => (address.parse::<IpAddr>().ok()?, length.parse::<u8>().ok()?),
+        None => {
+            let address = text.parse::<IpAddr>().ok()?;
+            (address, if address.is_ipv4() { 32 } else { 128 })
+        }
+    };
+    let max = if address.is_ipv4() { 32 } else { 128 };
+    (length <= max).then_some((address, length))
+}
+
+/// whether `inner` (an address or prefix) lies inside `network`
+fn cidr_contains(network: (IpAddr, u8), inner: (IpAddr, u8)) -> bool {
+    let bits = |address: IpAddr| match address {
+        IpAddr::V4(v4) => (u32::from(v4) as u128, 32u32),
+        IpAddr::V6(v6) => (u128::from(v6), 128u32),
+    };
+    let ((network_bits, width), (inner_bits, inner_width)) = (bits(network.0), bits(inner.0));
+    if width != inner_width || inner.1 < network.1 {
+        return false;
+    }
+    let mask = if network.1 == 0 { 0 } else { (u128::MAX << (128 - network.1 as u32)) >> (128 - width) };
+    network_bits & mask == inner_bits & mask
+}
+
+/// case-insensitive glob match where `*` is any run of characters and `?` any single one
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // let the last '*' swallow one more character
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// `tag:Key=Value` or `tag:Key`, both sides globs; `missing` inverts it for --missing-tag
+#[derive(Debug, Clone)]
+struct TagFilter {
+    key: String,
+    value: Option<String>,
+    missing: bool,
+}
+
+impl TagFilter {
+    fn parse(term: &str) -> Result<Self, AzureSearchError> {
+        let (key, value) = match term.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim().to_string())),
+            None => (term.trim(), None),
+        };
+        if key.is_empty() {
+            return Err(AzureSearchError::usage(format!("tag: needs a key, as in tag:Environment=prod, got '{}'", term)));
+        }
+        Ok(Self { key: key.to_string(), value, missing: false })
+    }
+
+    fn matches(&self, resource: &Value) -> bool {
+        let tags = resource.get("tags").and_then(|t| t.as_object());
+        let found = tags.into_iter().flatten().any(|(key, value)| {
+            glob_matches(&self.key, key) && self.value.as_ref().is_none_or(|wanted| {
+                scalar_text(value).is_some_and(|text| glob_matches(wanted, &text))
+            })
+        });
+        found != self.missing
+    }
+}
+
+impl fmt::Display for TagFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.value, self.missing) {
+            (_, true) => write!(f, "missing tag {}", self.key),
+            (Some(value), false) => write!(f, "tag {}={}", self.key, value),
+            (None, false) => write!(f, "tag {}", self.key),
+        }
+    }
+}
+
+/// every tag key with its distinct values and how many resources carry each;
+/// keys and values group case-insensitively, keeping the first spelling seen
+fn tag_inventory(resources: &[Value]) -> Value {
+    let mut keys: Vec<(String, Vec<(String, usize)>)> = Vec::new();
+    for tags in resources.iter().filter_map(|r| r.get("tags").and_then(|t| t.as_object())) {
+        for (key, value) in tags {
+            let value = scalar_text(value).unwrap_or_default();
+            let index = match keys.iter().position(|(k, _)| k.eq_ignore_ascii_case(key)) {
+                Some(index) => index,
+                None => {
+                    keys.push((key.clone(), Vec::new()));
+                    keys.len() - 1
+                }
+            };
+            let values = &mut keys[index].1;
+            match values.iter_mut().find(|(v, _)| v.eq_ignore_ascii_case(&value)) {
+                Some((_, count)) => *count += 1,
+                None => values.push((value, 1)),
+            }
+        }
+    }
+
+    keys.sort_by_key(|(key, _)| key.to_lowercase());
+    let rows = keys.into_iter().flat_map(|(key, mut values)| {
+        values.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.to_lowercase().cmp(&b.to_lowercase())));
+        values.into_iter().map(move |(value, count)| serde_json::json!({ "key": key, "value": value, "resources": count }))
+    }).collect();
+    Value::Array(rows)
+}
+
+/// (location, sku, count) for one resource type
+type TypeBreakdown = Vec<(String, String, usize)>;
+
+/// live resources grouped by type, then location and SKU, with counts; `alias` is the
+/// first alias that selects the type, empty when there is none
+fn type_summary(resources: &[Value], aliases: &AliasTable) -> Value {
+    let text = |resource: &Value, path: &str| get_field(resource, path).and_then(|v| scalar_text(&v)).unwrap_or_default();
+    let mut groups: Vec<(String, TypeBreakdown)> = Vec::new();
+    for resource in resources {
+        let resource_type = text(resource, "type");
+        let location = text(resource, "location");
+        let sku = get_field(resource, "sku.name").or_else(|| get_field(resource, "sku.tier"))
+            .and_then(|v| scalar_text(&v))
+            .unwrap_or_default();
+
+        let index = match groups.iter().position(|(t, _)| t.eq_ignore_ascii_case(&resource_type)) {
+            Some(index) => index,
+            None => {
+                groups.push((resource_type, Vec::new()));
+                groups.len() - 1
+            }
+        };
+        let breakdown = &mut groups[index].1;
+        match breakdown.iter_mut().find(|(l, k, _)| l.eq_ignore_ascii_case(&location) && *k == sku) {
+            Some((_, _, count)) => *count += 1,
+            None => breakdown.push((location, sku, 1)),
+        }
+    }
+
+    groups.sort_by_key(|(resource_type, _)| resource_type.to_lowercase());
+    let rows = groups.into_iter().flat_map(|(resource_type, mut breakdown)| {
+        let alias = aliases.entries.iter()
+            .find(|e| e.provider_type.eq_ignore_ascii_case(&resource_type))
+            .and_then(|e| e.aliases.first().cloned())
+            .unwrap_or_default();
+        let total: usize = breakdown.iter().map(|(_, _, count)| count).sum();
+        breakdown.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (&a.0, &a.1).cmp(&(&b.0, &b.1))));
+        breakdown.into_iter().map(move |(location, sku, count)| serde_json::json!({
+            "type": resource_type,
+            "alias": alias,
+            "location": location,
+            "sku": sku,
+            "resources": count,
+            "typeTotal": total,
+        }))
+    }).collect();
+    Value::Array(rows)
+}
+
+/// the lowercased subscription and resource group an ARM id belongs to
+fn id_scope(id: &str) -> Option<(String, String)> {
+    let segments: Vec<&str> = id.split('/').filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        [subscriptions, subscription, groups, rg, ..]
+            if subscriptions.eq_ignore_ascii_case("subscriptions") && groups.eq_ignore_ascii_case("resourceGroups") =>
+        {
+            Some((subscription.to_lowercase(), rg.to_lowercase()))
+        }
+        _ => None,
+    }
+}
+
+/// one row per resource group with its location, provisioning state, tags and,
+/// given `resources`, how many of them are in it; subscription tags from a fan-out are kept
+fn group_summary(groups: &[Value], resources: Option<&[Value]>) -> Value {
+    let mut counts: HashMap<(String, String), usize> = HashMap::new();
+    for resource in resources.unwrap_or_default() {
+        if let Some(scope) = resource.get("id").and_then(|id| id.as_str()).and_then(id_scope) {
+            *counts.entry(scope).or_default() += 1;
+        }
+    }
+
+    let rows: Vec<Value> = groups.iter()
+        .map(|group| {
+            let scope = group.get("id").and_then(|id| id.as_str()).and_then(id_scope);
+            let mut row = serde_json::json!({
+                "name": group.get("name").cloned().unwrap_or(Value::Null),
+                "location": group.get("location").cloned().unwrap_or(Value::Null),
+                "provisioningState": group.pointer("/properties/provisioningState").cloned().unwrap_or(Value::Null),
+                "tags": group.get("tags").cloned().unwrap_or_else(|| serde_json::json!({})),
+            });
+            if resources.is_some() {
+                row["resources"] = scope.and_then(|scope| counts.get(&scope).copied()).unwrap_or(0).into();
+            }
+            for key in ["subscriptionId", "subscriptionDisplayName"] {
+                if let Some(value) = group.get(key) {
+                    row[key] = value.clone();
+                }
+            }
+            row
+        })
+        .collect();
+    serde_json::json!({ "value": rows })
+}
+
+/// filter prefixes accepted after a listing or a single resource
+const FILTER_PREFIXES: &[&str] = &["search:", "subsearch:", "regex:", "subregex:", "exact:", "field:", "where:", "tag:"];
+
+/// a parsed `search:`, `subsearch:`, `regex:`, `subregex:`, `exact:` or `field:` argument
+#[derive(Debug, Clone)]
+enum Filter {
+    /// whole resources that match
+    Search(SearchExpr),
+    /// just the matching fields of matching resources, by path
+    Subsearch(SearchExpr),
+    Field(String),
+    /// resources passing a field predicate
+    Where(Predicate),
+    /// resources with (or, from --missing-tag, without) a matching tag
+    Tag(TagFilter),
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Search(expr) | Filter::Subsearch(expr) => write!(f, "{}", expr),
+            Filter::Field(path) => write!(f, "{}", path),
+            Filter::Where(predicate) => write!(f, "{}", predicate.text),
+            Filter::Tag(tag) => write!(f, "{}", tag),
+        }
+    }
+}
+
+impl Filter {
+    fn parse(arg: &str, target: MatchTarget) -> Result<Self, AzureSearchError> {
+        let (mode, term) = arg.split_once(':').unwrap_or(("", arg));
+        let filter = match mode {
+            "search" => Filter::Search(SearchExpr::parse(term, target)?),
+            "subsearch" => Filter::Subsearch(SearchExpr::parse(term, target)?),
+            "regex" => Filter::Search(Matcher::regex(term)?.with_target(target).into()),
+            "subregex" => Filter::Subsearch(Matcher::regex(term)?.with_target(target).into()),
+            "exact" => Filter::Search(Matcher::exact(term).with_target(target).into()),
+            "field" => {
+                parse_field_path(term)?;
+                Filter::Field(term.to_string())
+            }
+            "where" => Filter::Where(Predicate::parse(term)?),
+            "tag" => Filter::Tag(TagFilter::parse(term)?),
+            _ => return Err(AzureSearchError::usage(format!(
+                "Expected search:, subsearch:, regex:, subregex:, exact:, where:, tag: or field:, got '{}'",
+                arg
+            ))),
+        };
+        Ok(filter)
+    }
+
+    /// whether a single resource passes; only search and where: filters drop resources
+    fn keeps(&self, resource: &Value) -> bool {
+        match self {
+            Filter::Search(expr) => expr.matches(resource),
+            Filter::Where(predicate) => predicate.matches(resource),
+            Filter::Tag(tag) => tag.matches(resource),
+            Filter::Subsearch(_) | Filter::Field(_) => true,
+        }
+    }
+}
+
+/// separate the filters that drop resources from the one, if any, that reshapes the output
+fn split_filters(filters: &[Filter]) -> Result<(Vec<&Filter>, Option<&Filter>), AzureSearchError> {
+    let (shaping, narrowing): (Vec<&Filter>, Vec<&Filter>) = filters.iter()
+        .partition(|f| matches!(f, Filter::Subsearch(_) | Filter::Field(_)));
+    if shaping.len() > 1 {
+        return Err(AzureSearchError::usage("Only one subsearch:, subregex: or field: filter can be used at a time"));
+    }
+    Ok((narrowing, shaping.first().copied()))
+}
+
+/// parse trailing filter arguments, adding one for each --missing-tag
+fn parse_filters(args: &[String], options: &Options) -> Result<Vec<Filter>, AzureSearchError> {
+    let mut filters = args.iter()
+        .map(|f| Filter::parse(f, options.match_target))
+        .collect::<Result<Vec<_>, _>>()?;
+    for key in &options.missing_tags {
+        filters.push(Filter::Tag(TagFilter { missing: true, ..TagFilter::parse(key)? }));
+    }
+    Ok(filters)
+}
+
+/// keep the resources of a listing that pass every narrowing filter, as `{"value": [...]}`
+fn apply_filters(mut data: Value, narrowing: &[&Filter]) -> Value {
+    for filter in narrowing {
+        let kept = match filter {
+            Filter::Search(expr) => search_json(&data, expr),
+            _ => Value::Array(output_rows(&data).into_iter().filter(|r| filter.keeps(r)).collect()),
+        };
+        data = serde_json::json!({ "value": kept });
+    }
+    data
+}
+
+/// the narrowing filters as one clause, e.g. "location=eastus AND linux"
+fn describe_filters(narrowing: &[&Filter]) -> String {
+    narrowing.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(" AND ")
+}
+
+fn search_json(data: &Value, expr: &SearchExpr) -> Value {
+    let mut results = Vec::new();
+    
+    // if data is a list of resources (from list_resources), search each resource
+    if let Some(resources) = data.get("value").and_then(|v| v.as_array()) {
+        for resource in resources {
+            if expr.matches(resource) {
+                results.push(resource.clone());
+            }
+        }
+        return Value::Array(results);
+    }
+    
+    // if data is a single resource, search within it and return the whole resource if match found
+    if expr.matches(data) {
+        return data.clone();
+    }
+    
+    // no matches found
+    serde_json::json!([])
+}
+
+fn subsearch_json(data: &Value, expr: &SearchExpr) -> Value {
+    let mut results = serde_json::Map::new();
+    
+    // if data is a list of resources (from list_resources), search each resource
+    if let Some(resources) = data.get("value").and_then(|v| v.as_array()) {
+        for (resource_index, resource) in resources.iter().enumerate() {
+            let resource_name = resource.get("name")
+                .and_then(|n| n.as_str())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| format!("resource_{}", resource_index));
+            
+            subsearch_resource(resource, &resource_name, expr, &mut results);
+        }
+        return Value::Object(results);
+    }
+    
+    // if data is a single resource, search within it
+    subsearch_resource(data, "resource", expr, &mut results);
+    Value::Object(results)
+}
+
+/// report the fields matching each positive term, for resources the whole expression matches
+fn subsearch_resource(resource: &Value, resource_name: &str, expr: &SearchExpr, results: &mut serde_json::Map<String, Value>) {
+    if !expr.matches(resource) {
+        return;
+    }
+    for matcher in expr.positive_terms() {
+        subsearch_recursive_flat(resource, resource_name, "", None, matcher, results);
+    }
+}
+
+fn subsearch_recursive_flat(data: &Value, resource_name: &str, path: &str, parent_key: Option<&str>, matcher: &Matcher, results: &mut serde_json::Map<String, Value>) {
+    match data {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let current_path = if path.is_empty() { 
+                    format!("{}.{}", resource_name, key)
+                } else { 
+                    format!("{}.{}", path, key) 
+                };
+                
+                // check if key or scalar value matches
+                if matcher.entry_matches(key, value) {
+                    results.insert(current_path.clone(), value.clone());
+                }
+                
+                // recurse into nested objects/arrays
+                subsearch_recursive_flat(value, resource_name, &current_path, Some(key), matcher, results);
+            }
+        }
+        Value::Array(arr) => {
+            for (index, item) in arr.iter().enumerate() {
+                let current_path = if path.is_empty() { 
+                    format!("{}[{}]", resource_name, index)
+                } else { 
+                    format!("{}[{}]", path, index) 
+                };
+                
+                // array elements are reported under their indexed path
+                if matcher.element_matches(parent_key, item) {
+                    results.insert(current_path.clone(), item.clone());
+                }
+                subsearch_recursive_flat(item, resource_name, &current_path, None, matcher, results);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn resource_contains_term(resource: &Value, matcher: &Matcher) -> bool {
+    search_recursive_bool(resource, None, matcher)
+}
+
+fn search_recursive_bool(data: &Value, parent_key: Option<&str>, matcher: &Matcher) -> bool {
+    match data {
+        Value::Object(map) => {
+            for (key, value) in map {
+                // check if key or scalar value matches
+                if matcher.entry_matches(key, value) {
+                    return true;
+                }
+                
+                // recurse into nested objects/arrays
+                if search_recursive_bool(value, Some(key), matcher) {
+                    return true;
+                }
+            }
+        }
+        Value::Array(arr) => {
+            for item in arr {
+                if matcher.element_matches(parent_key, item) || search_recursive_bool(item, None, matcher) {
+                    return true;
+                }
+            }
+        }
+        _ => {}
+    }
+    false
+}
+
+/// ARM endpoint, token authority and token audience for one Azure cloud
+#[derive(Debug, Clone, PartialEq)]
+struct CloudProfile {
+    name: String,
+    arm_endpoint: String,
+    authority: String,
+    audience: String,
+}
+
+impl Default for CloudProfile {
+    fn default() -> Self {
+        Self::named("AzurePublic").unwrap()
+    }
+}
+
+impl CloudProfile {
+    /// built-in clouds, accepting the names used by `az cloud list` as well as short forms
+    fn named(name: &str) -> Option<Self> {
+        let (name, arm_endpoint, authority) = match name.to_lowercase().as_str() {
+            "azurepublic" | "azurecloud" | "public" => (
+                "AzurePublic",
+                "https://management.azure.com",
+                DEFAULT_AUTHORITY,
+            ),
+            "azureusgovernment" | "usgov" | "usgovernment" => (
+                "AzureUSGovernment",
+                "https://management.usgovcloudapi.net",
+                "https://login.microsoftonline.us",
+            ),
+            "azurechina" | "azurechinacloud" | "china" => (
+                "AzureChina",
+                "https://management.chinacloudapi.cn",
+                "https://login.chinacloudapi.cn",
+            ),
+            _ => return None,
+        };
+
+        Some(Self {
+            name: name.to_string(),
+            arm_endpoint: arm_endpoint.to_string(),
+            authority: authority.to_string(),
+            audience: format!("{}/", arm_endpoint),
+        })
+    }
+
+    /// build a profile from a name plus optional endpoint overrides; a custom
+    /// cloud only needs an ARM endpoint and defaults its audience to match
+    fn resolve(
+        name: Option<&str>,
+        arm_endpoint: Option<&str>,
+        authority: Option<&str>,
+        audience: Option<&str>,
+    ) -> Result<Self, AzureSearchError> {
+        let mut profile = match name {
+            Some(name) if name.eq_ignore_ascii_case("custom") => {
+                let arm_endpoint = arm_endpoint
+                    .ok_or_else(|| AzureSearchError::Config { message: "a custom cloud needs an ARM endpoint (--arm-endpoint)".to_string() })?;
+                Self {
+                    name: "Custom".to_string(),
+                    arm_endpoint: arm_endpoint.to_string(),
+                    authority: DEFAULT_AUTHORITY.to_string(),
+                    audience: format!("{}/", arm_endpoint.trim_end_matches('/')),
+                }
+            }
+            Some(name) => Self::named(name).ok_or_else(|| AzureSearchError::Config {
+                message: format!("unknown cloud '{}'. Use AzurePublic, AzureUSGovernment, AzureChina or custom.", name),
+            })?,
+            None => Self::default(),
+        };
+
+        if let Some(arm_endpoint) = arm_endpoint {
+            profile.arm_endpoint = arm_endpoint.to_string();
+        }
+        if let Some(authority) = authority {
+            profile.authority = authority.to_string();
+        }
+        if let Some(audience) = audience {
+            profile.audience = audience.to_string();
+        }
+        profile.arm_endpoint = profile.arm_endpoint.trim_end_matches('/').to_string();
+
+        Ok(profile)
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("azure_search").join("config.json"))
+}
+
+/// load the json config file; a missing default file is fine, a missing explicit one is not
+fn load_config(explicit: Option<&str>) -> Result<Value, AzureSearchError> {
+    let path = match explicit {
+        Some(path) => PathBuf::from(path),
+        None => match default_config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Value::Null),
+        },
+    };
+
+    let contents = std::fs::read_to_string(&path).map_err(|e| AzureSearchError::Config {
+        message: format!("cannot read config '{}': {}", path.display(), e),
+    })?;
+    serde_json::from_str(&contents).map_err(|e| AzureSearchError::Config {
+        message: format!("invalid json in config '{}': {}", path.display(), e),
+    })
+}
+
+/// how many subscriptions are queried at once unless --concurrency says otherwise
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// options that may appear anywhere on the command line, stripped before positional parsing
+#[derive(Debug, Default)]
+struct Options {
+    page_limit: PageLimit,
+    tenant: Option<String>,
+    token_cache: Option<PathBuf>,
+    auth_mode: AuthMode,
+    cloud: CloudProfile,
+    config: Value,
+    imds_endpoint: String,
+    identity_client_id: Option<String>,
+    retry: RetryPolicy,
+    verbose: bool,
+    management_groups: Vec<String>,
+    concurrency: usize,
+    output: Output,
+    match_target: MatchTarget,
+    missing_tags: Vec<String>,
+    target: Target,
+    expand_refs: Option<usize>,
+    graph_format: GraphFormat,
+    counts: bool,
+}
+
+fn parse_count(name: &str, value: Option<String>) -> Result<usize, AzureSearchError> {
+    let value = option_value(name, value)?;
+    value.parse::<usize>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| AzureSearchError::usage(format!("Option '--{}' expects a positive number, got '{}'", name, value)))
+}
+
+/// resolve the token cache location: `--token-cache[=path]` wins over
+/// AZURE_SEARCH_TOKEN_CACHE, which may be a path or "1"/"true" for the default
+fn token_cache_path(flag: Option<Option<String>>) -> Result<Option<PathBuf>, AzureSearchError> {
+    let requested = match flag {
+        Some(path) => Some(path),
+        None => env::var("AZURE_SEARCH_TOKEN_CACHE")
+            .ok()
+            .filter(|v| !v.is_empty() && v != "0" && !v.eq_ignore_ascii_case("false"))
+            .map(|v| if v == "1" || v.eq_ignore_ascii_case("true") { None } else { Some(v) }),
+    };
+
+    match requested {
+        None => Ok(None),
+        Some(Some(path)) => Ok(Some(PathBuf::from(path))),
+        Some(None) => TokenCache::default_path()
+            .map(Some)
+            .ok_or_else(|| AzureSearchError::Config {
+                message: "could not determine a cache directory for --token-cache; pass --token-cache=<path>".to_string(),
+            }),
+    }
+}
+
+fn option_value(name: &str, value: Option<String>) -> Result<String, AzureSearchError> {
+    value.ok_or_else(|| AzureSearchError::usage(format!("Option '--{}' requires a value", name)))
+}
+
+fn env_value(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn parse_options(args: Vec<String>) -> Result<(Options, Vec<String>), AzureSearchError> {
+    let mut options = Options {
+        tenant: env_value("AZURE_TENANT_ID"),
+        imds_endpoint: env_value("AZURE_POD_IDENTITY_AUTHORITY_HOST")
+            .unwrap_or_else(|| DEFAULT_IMDS_ENDPOINT.to_string()),
+        ..Options::default()
+    };
+    options.concurrency = DEFAULT_CONCURRENCY;
+    let mut token_cache_flag = None;
+    let mut config_flag = None;
+    let mut cloud_flag = None;
+    let mut arm_endpoint_flag = None;
+    let mut authority_flag = None;
+    let mut audience_flag = None;
+    let mut positional = Vec::new();
+    let mut iter = args.into_iter();
+
+    while let Some(arg) = iter.next() {
+        if arg == "-v" {
+            options.verbose = true;
+            continue;
+        }
+        if arg == "-o" {
+            options.output.format = OutputFormat::parse(&option_value("o", iter.next())?)?;
+            continue;
+        }
+
+        let Some(flag) = arg.strip_prefix("--") else {
+            positional.push(arg);
+            continue;
+        };
+
+        // accept both "--name value" and "--name=value"
+        let (name, inline_value) = match flag.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (flag.to_string(), None),
+        };
+
+        match name.as_str() {
+            "max-pages" => {
+                let value = inline_value.or_else(|| iter.next());
+                options.page_limit.max_pages = Some(parse_count(&name, value)?);
+            }
+            "max-items" => {
+                let value = inline_value.or_else(|| iter.next());
+                options.page_limit.max_items = Some(parse_count(&name, value)?);
+            }
+            "tenant" => {
+                options.tenant = Some(option_value(&name, inline_value.or_else(|| iter.next()))?);
+            }
+            "auth" => {
+                options.auth_mode = AuthMode::parse(&option_value(&name, inline_value.or_else(|| iter.next()))?)?;
+            }
+            "authority" => authority_flag = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "cloud" => cloud_flag = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "arm-endpoint" => arm_endpoint_flag = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "audience" => audience_flag = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "config" => config_flag = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "imds-endpoint" => {
+                options.imds_endpoint = option_value(&name, inline_value.or_else(|| iter.next()))?;
+            }
+            "identity-client-id" => {
+                options.identity_client_id = Some(option_value(&name, inline_value.or_else(|| iter.next()))?);
+            }
+            "max-attempts" => {
+                let attempts = parse_count(&name, inline_value.or_else(|| iter.next()))?;
+                options.retry.max_attempts = u32::try_from(attempts).unwrap_or(u32::MAX);
+            }
+            "retry-deadline" => {
+                let seconds = parse_count(&name, inline_value.or_else(|| iter.next()))?;
+                options.retry.deadline = Duration::from_secs(seconds as u64);
+            }
+            "verbose" => options.verbose = true,
+            "counts" => options.counts = true,
+            "output" => {
+                options.output.format = OutputFormat::parse(&option_value(&name, inline_value.or_else(|| iter.next()))?)?;
+            }
+            "match" => {
+                options.match_target = MatchTarget::parse(&option_value(&name, inline_value.or_else(|| iter.next()))?)?;
+            }
+            "columns" => {
+                let columns = option_value(&name, inline_value.or_else(|| iter.next()))?;
+                options.output.columns = Some(columns.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect());
+            }
+            "format" => {
+                options.graph_format = GraphFormat::parse(&option_value(&name, inline_value.or_else(|| iter.next()))?)?;
+            }
+            "concurrency" => options.concurrency = parse_count(&name, inline_value.or_else(|| iter.next()))?,
+            "sub" | "subscription" => options.target.subscription = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "rg" | "resource-group" => options.target.rg = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "type" => options.target.resource_type = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "name" => options.target.name = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "query" => options.target.query = Some(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "missing-tag" => options.missing_tags.push(option_value(&name, inline_value.or_else(|| iter.next()))?),
+            "management-group" => {
+                let groups = option_value(&name, inline_value.or_else(|| iter.next()))?;
+                options.management_groups.extend(groups.split(',').map(|g| g.trim().to_string()).filter(|g| !g.is_empty()));
+            }
+            // the path is optional, so it is only taken from the "--token-cache=path" form
+            "token-cache" => token_cache_flag = Some(inline_value),
+            // likewise the depth, from "--expand-refs=N"
+            "expand-refs" => {
+                options.expand_refs = Some(match inline_value {
+                    Some(depth) => parse_count(&name, Some(depth))?,
+                    None => DEFAULT_EXPAND_DEPTH,
+                });
+            }
+            _ => return Err(AzureSearchError::usage(format!("Unknown option '--{}'", name))),
+        }
+    }
+
+    options.token_cache = token_cache_path(token_cache_flag)?;
+    options.config = load_config(config_flag.or_else(|| env_value("AZURE_SEARCH_CONFIG")).as_deref())?;
+
+    // each cloud setting comes from the flag, then the environment, then the config file
+    let config_cloud = options.config.get("cloud").cloned().unwrap_or(Value::Null);
+    let config_setting = |key: &str| match &config_cloud {
+        Value::String(name) if key == "name" => Some(name.clone()),
+        Value::Object(map) => map.get(key).and_then(|v| v.as_str()).map(|v| v.to_string()),
+        _ => None,
+    };
+    let cloud_name = cloud_flag.or_else(|| env_value("AZURE_SEARCH_CLOUD")).or_else(|| config_setting("name"));
+    let arm_endpoint = arm_endpoint_flag.or_else(|| env_value("AZURE_SEARCH_ARM_ENDPOINT")).or_else(|| config_setting("armEndpoint"));
+    let authority = authority_flag.or_else(|| env_value("AZURE_AUTHORITY_HOST")).or_else(|| config_setting("authority"));
+    let audience = audience_flag.or_else(|| env_value("AZURE_SEARCH_AUDIENCE")).or_else(|| config_setting("audience"));
+
+    options.cloud = CloudProfile::resolve(
+        cloud_name.as_deref(),
+        arm_endpoint.as_deref(),
+        authority.as_deref(),
+        audience.as_deref(),
+    )?;
+
+    Ok((options, positional))
+}
+
+/// how results are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum OutputFormat {
+    #[default]
+    Json,
+    Table,
+    Csv,
+    Yaml,
+    Ndjson,
+}
+
+impl OutputFormat {
+    fn parse(format: &str) -> Result<Self, AzureSearchError> {
+        match format.to_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "table" => Ok(OutputFormat::Table),
+            "csv" => Ok(OutputFormat::Csv),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            other => Err(AzureSearchError::usage(format!(
+                "Unknown output format '{}', expected json, table, csv, yaml or ndjson",
+                other
+            ))),
+        }
+    }
+}
+
+/// columns shown for resources in table and csv output when --columns is not given
+const DEFAULT_COLUMNS: &[&str] = &["name", "type", "location", "resourceGroup", "tags"];
+
+/// output format plus the columns picked with --columns
+#[derive(Debug, Clone, Default)]
+struct Output {
+    format: OutputFormat,
+    columns: Option<Vec<String>>,
+}
+
+impl Output {
+    /// print a listing, search result, single resource or field value
+    fn print(&self, value: &Value) {
+        let rendered = self.render(value);
+        if !rendered.is_empty() {
+            println!("{}", rendered);
+        }
+    }
+
+    /// print `subsearch:` matches, a map of field path to value
+    fn print_fields(&self, fields: &Value) {
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml if self.columns.is_none() => self.print(fields),
+            _ => {
+                let rows = fields.as_object().into_iter().flatten()
+                    .map(|(path, value)| serde_json::json!({ "path": path, "value": value }))
+                    .collect();
+                self.print(&Value::Array(rows));
+            }
+        }
+    }
+
+    /// json keeps the "no matches" message on stdout as before; other formats stay parseable
+    fn no_matches(&self, message: &str) {
+        if self.format == OutputFormat::Json {
+            println!("{}", message);
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+
+    fn render(&self, value: &Value) -> String {
+        match self.format {
+            OutputFormat::Json if self.columns.is_none() => serde_json::to_string_pretty(value).unwrap(),
+            OutputFormat::Yaml if self.columns.is_none() => yaml_string(value),
+            OutputFormat::Json => serde_json::to_string_pretty(&self.projected(value)).unwrap(),
+            OutputFormat::Yaml => yaml_string(&self.projected(value)),
+            OutputFormat::Ndjson => {
+                let rows = match self.columns {
+                    Some(_) => self.projected(value).as_array().cloned().unwrap_or_default(),
+                    None => output_rows(value),
+                };
+                rows.iter().map(|row| row.to_string()).collect::<Vec<_>>().join("\n")
+            }
+            OutputFormat::Table => {
+                let (columns, cells) = self.cells(value);
+                render_table(&columns, &cells)
+            }
+            OutputFormat::Csv => {
+                let (columns, cells) = self.cells(value);
+                std::iter::once(columns)
+                    .chain(cells)
+                    .map(|line| line.iter().map(|cell| csv_field(cell)).collect::<Vec<_>>().join(","))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+    }
+
+    fn columns_for(&self, rows: &[Value]) -> Vec<String> {
+        if let Some(columns) = &self.columns {
+            return columns.clone();
+        }
+        if rows.iter().all(|row| row.get("id").is_some_and(|id| id.is_string())) {
+            return DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect();
+        }
+
+        // anything else gets its own top-level keys, in order of first appearance
+        let mut columns: Vec<String> = Vec::new();
+        for row in rows {
+            for key in row.as_object().into_iter().flat_map(|obj| obj.keys()) {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+        columns
+    }
+
+    /// rows reduced to the selected columns, as json objects
+    fn projected(&self, value: &Value) -> Value {
+        let rows = output_rows(value);
+        let columns = self.columns_for(&rows);
+        Value::Array(rows.iter().map(|row| {
+            Value::Object(columns.iter().map(|c| (c.clone(), column_value(row, c).unwrap_or(Value::Null))).collect())
+        }).collect())
+    }
+
+    fn cells(&self, value: &Value) -> (Vec<String>, Vec<Vec<String>>) {
+        let rows = output_rows(value);
+        let columns = self.columns_for(&rows);
+        let cells = rows.iter()
+            .map(|row| columns.iter().map(|c| format_cell(column_value(row, c).as_ref())).collect())
+            .collect();
+        (columns, cells)
+    }
+}
+
+/// the rows of a result: the items of a listing or array, or the value itself;
+/// scalars become `{"value": ...}` so they still have a column
+fn output_rows(value: &Value) -> Vec<Value> {
+    let items = match value {
+        Value::Object(map) => match map.get("value") {
+            Some(Value::Array(items)) => items.clone(),
+            _ => vec![value.clone()],
+        },
+        Value::Array(items) => items.clone(),
+        _ => vec![value.clone()],
+    };
+    items.into_iter()
+        .map(|item| if item.is_object() { item } else { serde_json::json!({ "value": item }) })
+        .collect()
+}
+
+/// a column value by field path; `resourceGroup` falls back to the resource id
+fn column_value(row: &Value, column: &str) -> Option<Value> {
+    if let Some(value) = get_field(row, column) {
+        return Some(value);
+    }
+
+    if column == "resourceGroup" {
+        let id = row.get("id")?.as_str()?;
+        let mut segments = id.split('/');
+        segments.find(|s| s.eq_ignore_ascii_case("resourceGroups"))?;
+        return segments.next().map(|rg| Value::String(rg.to_string()));
+    }
+    None
+}
+
+/// a single table or csv cell; flat objects such as tags read as "key=value; key=value"
+fn format_cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Object(map)) if map.values().all(|v| !v.is_object() && !v.is_array()) => map.iter()
+            .map(|(k, v)| format!("{}={}", k, v.as_str().map(|s| s.to_string()).unwrap_or_else(|| v.to_string())))
+            .collect::<Vec<_>>()
+            .join("; "),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn render_table(columns: &[String], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[String]| {
+        cells.iter().zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = *width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    let separator: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+
+    std::iter::once(line(columns))
+        .chain(std::iter::once(line(&separator)))
+        .chain(rows.iter().map(|row| line(row)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+fn yaml_string(value: &Value) -> String {
+    serde_yaml::to_string(value).unwrap().trim_end().to_string()
+}
+
+fn print_listing(paged: PagedResult, output: &Output) {
+    paged.report();
+    output.print(&paged.data);
+}
+
+/// whether a trailing argument is a listing filter rather than a name
+fn is_filter(arg: &str) -> bool {
+    FILTER_PREFIXES.iter().any(|prefix| arg.starts_with(prefix))
+}
+
+/// print a listing through its post-filters: searches and where: clauses drop
+/// resources (all must pass), then subsearch: or field: picks what is shown
+fn print_filtered_listing(paged: PagedResult, filters: &[Filter], output: &Output) -> Result<(), AzureSearchError> {
+    if filters.is_empty() {
+        print_listing(paged, output);
+        return Ok(());
+    }
+
+    paged.report();
+    let (narrowing, shaping) = split_filters(filters)?;
+    let data = apply_filters(paged.data, &narrowing);
+
+    match shaping {
+        None => {
+            let results = data.get("value").cloned().unwrap_or(Value::Null);
+            if results.as_array().is_none_or(|arr| arr.is_empty()) {
+                let verb = if narrowing.iter().all(|f| matches!(f, Filter::Search(_))) { "containing" } else { "matching" };
+                output.no_matches(&format!("No resources found {} '{}'", verb, describe_filters(&narrowing)));
+            } else {
+                output.print(&results);
+            }
+        }
+        Some(Filter::Field(path)) => {
+            let rows = project_field(&data, path);
+            if rows.is_empty() {
+                output.no_matches(&format!("No resources have field '{}'", path));
+            } else {
+                output.print(&Value::Array(rows));
+            }
+        }
+        Some(Filter::Subsearch(expr)) => {
+            let results = subsearch_json(&data, expr);
+            if results.as_object().is_none_or(|obj| obj.is_empty()) {
+                output.no_matches(&format!("No resources found containing '{}'", expr));
+            } else {
+                output.print_fields(&results);
+            }
+        }
+        Some(_) => unreachable!("split_filters only returns subsearch and field filters"),
+    }
+    Ok(())
+}
+
+/// the first argument; anything else there is the deprecated positional form
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    List,
+    Get,
+    Search,
+    Types,
+    Tags,
+    Groups,
+    Subscriptions,
+    Graph,
+    Find,
+    Kql,
+}
+
+impl Command {
+    const ALL: &'static [(&'static str, Command)] = &[
+        ("list", Command::List),
+        ("get", Command::Get),
+        ("search", Command::Search),
+        ("types", Command::Types),
+        ("tags", Command::Tags),
+        ("groups", Command::Groups),
+        ("subscriptions", Command::Subscriptions),
+        ("graph", Command::Graph),
+        ("find", Command::Find),
+        ("kql", Command::Kql),
+    ];
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|(n, _)| *n == name).map(|(_, command)| *command)
+    }
+
+    fn name(self) -> &'static str {
+        Self::ALL.iter().find(|(_, c)| *c == self).map(|(n, _)| *n).unwrap_or_default()
+    }
+}
+
+/// what a command works on, from --sub, --rg, --type, --name and --query
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Target {
+    subscription: Option<String>,
+    rg: Option<String>,
+    resource_type: Option<String>,
+    name: Option<String>,
+    query: Option<String>,
+}
+
+impl Target {
+    /// fill the target from a resource id; flags that disagree with it are an error
+    fn apply_id(&mut self, id: &ResourceId) -> Result<(), AzureSearchError> {
+        let fields = [
+            ("--sub", &mut self.subscription, Some(id.subscription.clone())),
+            ("--rg", &mut self.rg, id.resource_group.clone()),
+            ("--type", &mut self.resource_type, id.resource_type()),
+            ("--name", &mut self.name, id.name()),
+        ];
+        for (flag, slot, value) in fields {
+            let Some(value) = value else { continue };
+            match slot {
+                Some(given) if !given.eq_ignore_ascii_case(&value) => {
+                    return Err(AzureSearchError::usage(format!(
+                        "{} '{}' does not match the resource id, which has '{}'",
+                        flag, given, value
+                    )));
+                }
+                _ => *slot = Some(value),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// a command, its target and the remaining arguments (filters, a search term or a field path)
+#[derive(Debug, Clone, PartialEq)]
+struct Invocation {
+    command: Command,
+    target: Target,
+    args: Vec<String>,
+}
+
+impl Invocation {
+    /// translate the old `<subscription> [rg] [type] [name] [field] [filters]` form;
+    /// `is_type` decides whether a lone second argument is a type or a group, which
+    /// is the guess the subcommands exist to avoid
+    fn from_legacy(args: &[String], mut target: Target, is_type: impl Fn(&str) -> bool) -> Result<Self, AzureSearchError> {
+        // [subscriptions] graph:<kql> [filters]
+        if let Some(index) = args.iter().position(|a| a.starts_with("graph:")).filter(|i| *i <= 1) {
+            if index == 1 {
+                target.subscription = Some(args[0].clone());
+            }
+            target.query = Some(args[index]["graph:".len()..].to_string());
+            return Ok(Invocation { command: Command::Kql, target, args: args[index + 1..].to_vec() });
+        }
+
+        let Some((subscription, rest)) = args.split_first() else {
+            return Err(AzureSearchError::usage("Missing command"));
+        };
+
+        // a resource id names the resource itself; a group or subscription id lists it
+        if ResourceId::looks_like(subscription) {
+            let command = if ResourceId::parse(subscription)?.namespace.is_some() { Command::Get } else { Command::List };
+            return Ok(Invocation { command, target, args: args.to_vec() });
+        }
+        target.subscription = Some(subscription.clone());
+
+        // trailing search, where: and field: filters apply to any form
+        let first_filter = (0..rest.len()).find(|&i| rest[i..].iter().all(|a| is_filter(a))).unwrap_or(rest.len());
+        let (names, filters) = rest.split_at(first_filter);
+        let mut args = filters.to_vec();
+        let report = |name: &str| Command::parse(name).filter(|c| matches!(c, Command::Tags | Command::Types));
+
+        let command = match names {
+            [] => Command::List,
+            [name] if name == "all" => Command::List,
+            [name] if report(name).is_some() => report(name).unwrap_or(Command::List),
+            [name] if is_type(name) => {
+                target.resource_type = Some(name.clone());
+                Command::List
+            }
+            [rg] => {
+                target.rg = Some(rg.clone());
+                Command::List
+            }
+            [rg, name] if report(name).is_some() => {
+                target.rg = Some(rg.clone());
+                report(name).unwrap_or(Command::List)
+            }
+            [rg, resource_type] => {
+                target.rg = Some(rg.clone());
+                target.resource_type = Some(resource_type.clone());
+                Command::List
+            }
+            [rg, resource_type, name, field @ ..] if field.len() <= 1 => {
+                target.rg = Some(rg.clone());
+                target.resource_type = Some(resource_type.clone());
+                target.name = Some(name.clone());
+                args.splice(0..0, field.iter().cloned());
+                Command::Get
+            }
+            _ => return Err(AzureSearchError::usage(format!(
+                "Too many arguments before the filters: {}",
+                names.join(" ")
+            ))),
+        };
+
+        Ok(Invocation { command, target, args })
+    }
+
+    /// the equivalent subcommand line, shown when the positional form is used
+    fn command_line(&self) -> String {
+        let flags = [
+            ("--sub", &self.target.subscription),
+            ("--rg", &self.target.rg),
+            ("--type", &self.target.resource_type),
+            ("--name", &self.target.name),
+            ("--query", &self.target.query),
+        ];
+        let mut parts = vec![self.command.name().to_string()];
+        for (flag, value) in flags {
+            if let Some(value) = value {
+                parts.push(flag.to_string());
+                parts.push(shell_quote(value));
+            }
+        }
+        parts.extend(self.args.iter().map(|a| shell_quote(a)));
+        parts.join(" ")
+    }
+}
+
+/// quote an argument for a POSIX shell when it has anything beyond plain word characters
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:,=@+%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// the subscription used when --sub is not given: AZURE_SUBSCRIPTION_ID, then the config file
+fn default_subscription(config: &Value) -> Option<String> {
+    env_value("AZURE_SUBSCRIPTION_ID")
+        .or_else(|| config.get("subscription").and_then(|s| s.as_str()).map(|s| s.to_string()))
+}
+
+fn unexpected_argument(command: Command, arg: &str) -> AzureSearchError {
+    AzureSearchError::usage(format!(
+        "Unexpected argument '{}' for '{}'; filters look like search:term, where:location=eastus, tag:Key=Value or field:path",
+        arg,
+        command.name()
+    ))
+}
+
+/// fetch resources across the subscriptions: everything, one type, one group,
+/// the children of the parent named by --name, or the one resource it names
+async fn fetch_listing(
+    client: &ArmClient,
+    subscriptions: &[SubscriptionRef],
+    options: &Options,
+    rg: Option<&str>,
+    resource_type: Option<&ResolvedType>,
+    parent: Option<&str>,
+) -> Result<PagedResult, AzureSearchError> {
+    let limit = options.page_limit;
+    let concurrency = options.concurrency;
+
+    match (rg, resource_type, parent) {
+        (Some(rg), Some(resource_type), Some(name)) if name.split('/').count() == resource_type.types.len() => {
+            // a full name, e.g. from a resource id, lists just that resource
+            let [subscription] = subscriptions else {
+                return Err(AzureSearchError::usage("Fetching a single resource needs exactly one subscription"));
+            };
+            let resource = get_resource_json(client, &subscription.id, rg, resource_type, name).await?;
+            Ok(PagedResult { data: serde_json::json!({ "value": [resource] }), pages: 1, truncated: false })
+        }
+        (Some(rg), Some(resource_type), Some(parent)) if resource_type.is_child() => {
+            let [subscription] = subscriptions else {
+                return Err(AzureSearchError::usage("Listing child resources needs exactly one subscription"));
+            };
+            list_child_resources(client, &subscription.id, rg, resource_type, parent, limit).await
+        }
+        (_, _, Some(_)) => Err(AzureSearchError::usage(
+            "--name on a listing needs --rg and --type, and names one resource or the parent of a child type, e.g. list --rg myRG --type Microsoft.Network/virtualNetworks/subnets --name myVNet",
+        )),
+        (None, None, None) => fan_out(subscriptions, concurrency, limit, |sub| async move {
+            list_all_resources(client, &sub, limit).await
+        }).await,
+        (None, Some(resource_type), None) => fan_out(subscriptions, concurrency, limit, |sub| async move {
+            list_resources_by_type(client, &sub, resource_type, limit).await
+        }).await,
+        (Some(rg), None, None) => fan_out(subscriptions, concurrency, limit, |sub| async move {
+            list_all_resources_in_rg(client, &sub, rg, limit).await
+        }).await,
+        (Some(rg), Some(resource_type), None) => fan_out(subscriptions, concurrency, limit, |sub| async move {
+            list_resources_in_rg(client, &sub, rg, resource_type, limit).await
+        }).await,
+    }
+}
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} <command> [--sub SUBSCRIPTION] [--rg GROUP] [--type TYPE] [--name NAME] [--query TEXT] [filters...] [options]", program);
+    eprintln!("Commands:");
+    eprintln!("  list     list resources: all of them, one --type, one --rg, or the children of --name");
+    eprintln!("  get      fetch one resource (--rg, --type and --name), optionally one field path");
+    eprintln!("  search   list resources matching a search expression, given as --query or arguments");
+    eprintln!("  types    summarise live resource types; with no --sub, show the type aliases");
+    eprintln!("  tags     list tag keys with their values and resource counts");
+    eprintln!("  groups   list resource groups with their location, state and tags; --counts adds resource counts");
+    eprintln!("  subscriptions  list the subscriptions the token can see, with their state and tenant");
+    eprintln!("  graph    export how resources reference each other as dot, mermaid or json (--format); --name starts from one resource");
+    eprintln!("  find     find resources by name (exact, prefix, or a glob with * and ?); one hit is fetched in full");
+    eprintln!("  kql      run an Azure Resource Graph query over --sub or --management-group");
+    eprintln!("Target:");
+    eprintln!("  --sub ID|NAME[,...]|'*'  subscriptions to query by id or display name, default AZURE_SUBSCRIPTION_ID or \"subscription\" in the config");
+    eprintln!("  --rg NAME  --type ALIAS|Namespace/type  --name NAME  --query TEXT");
+    eprintln!("  or a resource id (/subscriptions/.../resourceGroups/.../providers/...) in their place");
+    eprintln!("Filters: search:, subsearch:, regex:, subregex:, exact:, where:, tag:, field:");
+    eprintln!("Options: [--expand-refs[=DEPTH]] [--format dot|mermaid|json] [--counts] [--missing-tag KEY] [--match keys|values|both] [--max-pages N] [--max-items N] [--tenant ID] [--token-cache[=path]] [--auth auto|secret|certificate|workload|msi|cli] [--cloud NAME] [--arm-endpoint URL] [--authority URL] [--audience URL] [--config PATH] [--imds-endpoint URL] [--identity-client-id ID] [--max-attempts N] [--retry-deadline SECS] [-v|--verbose] [--management-group NAME] [--concurrency N] [-o|--output json|table|csv|yaml|ndjson] [--columns a,b,c]");
+    eprintln!("Examples:");
+    eprintln!("  {} list --sub 12345", program);
+    eprintln!("  {} list --sub 12345 --max-items 500", program);
+    eprintln!("  {} list --sub 12345 --type network", program);
+    eprintln!("  {} list --sub 12345 --rg network", program);
+    eprintln!("  {} list --sub 12345 --rg myRG --type Microsoft.ContainerInstance/containerGroups", program);
+    eprintln!("  {} list --sub 12345 --rg myRG --type Microsoft.Network/virtualNetworks/subnets --name myVNet", program);
+    eprintln!("  {} list --sub 12345 --type vms field:properties.hardwareProfile.vmSize", program);
+    eprintln!("  {} list --sub 12345 --type vms where:location=eastus 'where:properties.provisioningState!=Succeeded'", program);
+    eprintln!("  {} list --sub 12345 --type nics 'where:properties.ipConfigurations[*].properties.privateIPAddress in 10.0.0.0/16'", program);
+    eprintln!("  {} list --sub 12345 tag:Environment=prod* tag:ManagedBy=Terraform", program);
+    eprintln!("  {} list --sub 12345 --rg myRG --missing-tag Project", program);
+    eprintln!("  {} list --sub 12345,67890 --type vms --concurrency 4", program);
+    eprintln!("  {} list --sub 12345 --type vms -o table --columns name,location,properties.provisioningState", program);
+    eprintln!("  {} get --sub 12345 --rg myRG --type network --name myVNet", program);
+    eprintln!("  {} get --sub 12345 --rg myRG --type network --name myVNet 'properties.subnets[*].properties.addressPrefix'", program);
+    eprintln!("  {} get --sub 12345 --rg myRG --type network --name myVNet subsearch:address", program);
+    eprintln!("  {} get --sub 12345 --rg myRG --type vms --name vm1 --expand-refs=3", program);
+    eprintln!("  {} get --sub 12345 --rg myRG --type Microsoft.Network/virtualNetworks/subnets --name myVNet/default", program);
+    eprintln!("  {} get /subscriptions/12345/resourceGroups/myRG/providers/Microsoft.Network/networkInterfaces/nic1/ipConfigurations/ipconfig1", program);
+    eprintln!("  {} list /subscriptions/12345/resourceGroups/myRG search:coredns", program);
+    eprintln!("  {} search --sub 12345 Standard", program);
+    eprintln!("  {} search --sub '*' coredns", program);
+    eprintln!("  {} search --sub 12345 --type vms 'linux AND NOT test'", program);
+    eprintln!("  {} search --sub 12345 --type nsgs 'destinationPortRange>=1024'", program);
+    eprintln!("  {} list --sub 12345 --type publicips 'regex:^10\\.0\\.1\\.'", program);
+    eprintln!("  {} list --sub 12345 --type nics exact:10.0.1.4 --match values", program);
+    eprintln!("  {} list --sub 12345 --type nsgs 'subsearch:53 AND (Allow OR \"any port\")'", program);
+    eprintln!("  {} types", program);
+    eprintln!("  {} types --sub 12345 -o table", program);
+    eprintln!("  {} types --sub 12345 --rg myRG", program);
+    eprintln!("  {} tags --sub 12345 -o table", program);
+    eprintln!("  {} groups --sub 12345 -o table", program);
+    eprintln!("  {} groups --sub Production --counts where:resources=0", program);
+    eprintln!("  {} subscriptions -o table", program);
+    eprintln!("  {} graph --sub 12345 --rg rg-coredns-challenge > coredns.dot", program);
+    eprintln!("  {} graph --sub 12345 --rg myRG --type loadbalancers --name lb --format mermaid", program);
+    eprintln!("  {} find --sub 12345 vnet-coredns", program);
+    eprintln!("  {} find --sub '*' 'coredns-*' -o table", program);
+    eprintln!("  {} find --sub 12345 lb-coredns properties.frontendIPConfigurations[0].properties.publicIPAddress.id", program);
+    eprintln!("  {} kql --sub 12345,67890 \"Resources | where type =~ 'microsoft.network/publicipaddresses'\"", program);
+    eprintln!("  {} kql \"Resources | summarize count() by type\" --management-group my-mg", program);
+    eprintln!("The old positional form, {} <subscription> [group] [type] [name] [field] [filters...], still works but is deprecated.", program);
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn run() -> Result<(), AzureSearchError> {
+    let (options, args) = parse_options(env::args().collect())?;
+
+    if args.len() < 2 {
+        print_usage(&args[0]);
+        return Err(AzureSearchError::usage("Missing command"));
+    }
+
+    let aliases = AliasTable::load(&options.config)?;
+    let invocation = match Command::parse(&args[1]) {
+        Some(command) => Invocation { command, target: options.target.clone(), args: args[2..].to_vec() },
+        None => {
+            // a known alias or a '/' means a type; resource group names cannot contain '/'
+            let invocation = Invocation::from_legacy(&args[1..], options.target.clone(), |a| {
+                aliases.lookup(a).is_some() || a.contains('/')
+            })?;
+            eprintln!("Note: positional arguments are deprecated; use: {} {}", args[0], invocation.command_line());
+            invocation
+        }
+    };
+
+    execute(invocation, &options, &aliases).await
+}
+
+async fn execute(invocation: Invocation, options: &Options, aliases: &AliasTable) -> Result<(), AzureSearchError> {
+    let Invocation { command, mut target, mut args } = invocation;
+    let limit = options.page_limit;
+    let output = &options.output;
+    let match_target = options.match_target;
+
+    // a pasted resource id stands in for --sub, --rg, --type and --name
+    let (ids, rest): (Vec<String>, Vec<String>) = args.into_iter().partition(|a| ResourceId::looks_like(a));
+    args = rest;
+    match ids.as_slice() {
+        [] => {}
+        [id] => target.apply_id(&ResourceId::parse(id)?)?,
+        _ => return Err(AzureSearchError::usage("Only one resource id can be given")),
+    }
+
+    if options.expand_refs.is_some() && !matches!(command, Command::Get | Command::Find) {
+        return Err(AzureSearchError::usage("--expand-refs applies to single resources fetched with 'get' or 'find'"));
+    }
+
+    // with no subscription, group or type asked for, `types` shows the alias table
+    if command == Command::Types && target.subscription.is_none() && target.rg.is_none() && target.resource_type.is_none() {
+        aliases.print();
+        return Ok(());
+    }
+
+    let subscription_spec = target.subscription.clone().or_else(|| default_subscription(&options.config));
+
+    // the subscriptions themselves, which need no --sub
+    if command == Command::Subscriptions {
+        if let Some(word) = args.iter().find(|a| !is_filter(a)) {
+            return Err(unexpected_argument(command, word));
+        }
+        let filters = parse_filters(&args, options)?;
+        let client = ArmClient::new(options)?;
+        let paged = list_subscription_pages(&client, limit).await?;
+        let data = subscription_rows(&paged.data);
+        return print_filtered_listing(PagedResult { data, ..paged }, &filters, output);
+    }
+
+    // Resource Graph query: the query is --query or the first argument, the rest are filters
+    if command == Command::Kql {
+        let query = match target.query {
+            Some(query) => query,
+            None if args.first().is_some_and(|a| !is_filter(a)) => args.remove(0),
+            None => return Err(AzureSearchError::usage(
+                "'kql' needs a query, e.g. kql --sub 12345 \"Resources | summarize count() by type\"",
+            )),
+        };
+        let filters = parse_filters(&args, options)?;
+
+        let client = ArmClient::new(options)?;
+        let scope = if !options.management_groups.is_empty() {
+            GraphScope::ManagementGroups(options.management_groups.clone())
+        } else if let Some(spec) = &subscription_spec {
+            let subscriptions = resolve_subscriptions(&client, spec).await?;
+            GraphScope::Subscriptions(subscriptions.into_iter().map(|sub| sub.id).collect())
+        } else {
+            return Err(AzureSearchError::usage("'kql' needs --sub <subscription> or --management-group"));
+        };
+        let paged = query_resource_graph(&client, &query, &scope, limit).await?;
+        return print_filtered_listing(paged, &filters, output);
+    }
+
+    // filter arguments apply to every command; other words are a search term or a field path
+    let (mut words, filter_args): (Vec<String>, Vec<String>) = args.into_iter().partition(|a| !is_filter(a));
+    let mut filters = parse_filters(&filter_args, options)?;
+    let query = target.query.as_deref();
+
+    // find takes the name as --name or its first argument
+    let pattern = match (command, target.name.take()) {
+        (Command::Find, Some(name)) => Some(name),
+        (Command::Find, None) if !words.is_empty() => Some(words.remove(0)),
+        (Command::Find, None) => return Err(AzureSearchError::usage("'find' needs a name, e.g. find --sub 12345 'coredns-*'")),
+        (_, name) => {
+            target.name = name;
+            None
+        }
+    };
+
+    match command {
+        Command::Search => {
+            if let (Some(_), Some(word)) = (query, words.first()) {
+                return Err(unexpected_argument(command, word));
+            }
+            let term = query.map(|q| q.to_string())
+                .or_else(|| (!words.is_empty()).then(|| words.join(" ")))
+                .ok_or_else(|| AzureSearchError::usage("'search' needs a term, e.g. search --sub 12345 'linux AND NOT test'"))?;
+            filters.insert(0, Filter::Search(SearchExpr::parse(&term, match_target)?));
+        }
+        Command::Get | Command::Find => {
+            // a plain path is a field query; --query may also hold a filter
+            for field in words.iter().map(|w| w.as_str()).chain(query) {
+                if is_filter(field) {
+                    filters.push(Filter::parse(field, match_target)?);
+                } else {
+                    parse_field_path(field)?;
+                    filters.push(Filter::Field(field.to_string()));
+                }
+            }
+        }
+        _ => {
+            if let Some(word) = words.first() {
+                return Err(unexpected_argument(command, word));
+            }
+            // --query is a filter, or a search expression without a prefix
+            if let Some(query) = query {
+                filters.push(if is_filter(query) {
+                    Filter::parse(query, match_target)?
+                } else {
+                    Filter::Search(SearchExpr::parse(query, match_target)?)
+                });
+            }
+        }
+    }
+
+    let subscription_spec = subscription_spec.ok_or_else(|| AzureSearchError::usage(format!(
+        "'{}' needs --sub <subscription> (or AZURE_SUBSCRIPTION_ID)",
+        command.name()
+    )))?;
+    let client = ArmClient::new(options)?;
+    let client = &client;
+    let subscriptions = resolve_subscriptions(client, &subscription_spec).await?;
+
+    // an explicit --type must resolve; it is never mistaken for a resource group
+    let resource_type = match &target.resource_type {
+        Some(resource_type) => Some(resolve_resource_type(client, aliases, resource_type).await?),
+        None => None,
+    };
+    let rg = target.rg.as_deref();
+    let name = target.name.as_deref();
+
+    match command {
+        Command::Groups => {
+            if rg.is_some() || resource_type.is_some() || name.is_some() {
+                return Err(AzureSearchError::usage("'groups' lists every resource group; it does not take --rg, --type or --name"));
+            }
+            let paged = fan_out(&subscriptions, options.concurrency, limit, |sub| async move {
+                list_resource_groups(client, &sub, limit).await
+            }).await?;
+            // counting means listing every resource, so it is opt-in and bounded by the page limits
+            let resources = if options.counts {
+                let resources = fan_out(&subscriptions, options.concurrency, limit, |sub| async move {
+                    list_all_resources(client, &sub, limit).await
+                }).await?;
+                if resources.truncated {
+                    eprintln!("Warning: resource counts only cover the first {} resource(s) (page/item limit)", output_rows(&resources.data).len());
+                }
+                Some(output_rows(&resources.data))
+            } else {
+                None
+            };
+            let data = group_summary(&output_rows(&paged.data), resources.as_deref());
+            print_filtered_listing(PagedResult { data, ..paged }, &filters, output)
+        }
+        Command::Tags | Command::Types => {
+            // tag inventory or type summary across the subscriptions, a group, a type or one resource
+            let (narrowing, shaping) = split_filters(&filters)?;
+            if shaping.is_some() {
+                return Err(AzureSearchError::usage(format!("'{}' only takes search, where: and tag: filters", command.name())));
+            }
+            let paged = fetch_listing(client, &subscriptions, options, rg, resource_type.as_ref(), name).await?;
+            paged.report();
+            let resources = output_rows(&apply_filters(paged.data, &narrowing));
+            if command == Command::Tags {
+                output.print(&tag_inventory(&resources));
+            } else {
+                output.print(&type_summary(&resources, aliases));
+            }
+            Ok(())
+        }
+        Command::Graph => {
+            let (narrowing, shaping) = split_filters(&filters)?;
+            if shaping.is_some() {
+                return Err(AzureSearchError::usage("'graph' only takes search, where: and tag: filters"));
+            }
+            // with --name, --type only helps pick the start; the whole scope is still graphed
+            let listing_type = if name.is_some() { None } else { resource_type.as_ref() };
+            let paged = fetch_listing(client, &subscriptions, options, rg, listing_type, None).await?;
+            paged.report();
+            let listed = output_rows(&apply_filters(paged.data, &narrowing));
+
+            // listings leave out properties, so fetch each resource in full
+            eprintln!("Fetching {} resource(s) for their references", listed.len());
+            let resources: Vec<Value> = stream::iter(listed.into_iter().map(|resource| async move {
+                let id = resource.get("id").and_then(|id| id.as_str()).unwrap_or_default().to_string();
+                match get_resource_by_id(client, aliases, &id).await {
+                    Ok(full) => full,
+                    Err(e) => {
+                        eprintln!("Warning: could not fetch '{}': {}", id, e);
+                        resource
+                    }
+                }
+            }))
+                .buffered(options.concurrency.max(1))
+                .collect()
+                .await;
+
+            let mut graph = ResourceGraph::build(&resources);
+            if let Some(name) = name {
+                graph = graph.reachable_from(graph.select(name, resource_type.as_ref())?);
+            }
+            match options.graph_format {
+                GraphFormat::Dot => print!("{}", graph.to_dot()),
+                GraphFormat::Mermaid => print!("{}", graph.to_mermaid()),
+                GraphFormat::Json => output.print(&graph.to_json()),
+            }
+            Ok(())
+        }
+        Command::List | Command::Search => {
+            let paged = fetch_listing(client, &subscriptions, options, rg, resource_type.as_ref(), name).await?;
+            print_filtered_listing(paged, &filters, output)
+        }
+        Command::Get => {
+            let (Some(rg), Some(resource_type), Some(name)) = (rg, resource_type, name) else {
+                let missing: Vec<&str> = [
+                    ("--rg", target.rg.is_none()),
+                    ("--type", target.resource_type.is_none()),
+                    ("--name", target.name.is_none()),
+                ].iter().filter(|(_, missing)| *missing).map(|(flag, _)| *flag).collect();
+                return Err(AzureSearchError::usage(format!(
+                    "'get' needs --rg, --type and --name; missing {}",
+                    missing.join(", ")
+                )));
+            };
+            let [subscription] = subscriptions.as_slice() else {
+                return Err(AzureSearchError::usage("Fetching a single resource needs exactly one subscription"));
+            };
+
+            // a parent name one level short of a child type lists that parent's children
+            if resource_type.is_child() && name.split('/').count() + 1 == resource_type.types.len() {
+                let paged = list_child_resources(client, &subscription.id, rg, &resource_type, name, limit).await?;
+                return print_filtered_listing(paged, &filters, output);
+            }
+
+            let data = get_resource_json(client, &subscription.id, rg, &resource_type, name).await?;
+            show_resource(client, aliases, options, data, &filters).await
+        }
+        Command::Find => {
+            let pattern = pattern.unwrap_or_default();
+            let (narrowing, _) = split_filters(&filters)?;
+            let paged = fetch_listing(client, &subscriptions, options, rg, resource_type.as_ref(), None).await?;
+            paged.report();
+            let hits = find_by_name(&output_rows(&apply_filters(paged.data, &narrowing)), &pattern);
+
+            match hits.as_slice() {
+                [] => Err(AzureSearchError::NotFound { message: format!("No resource is named '{}'", pattern) }),
+                [hit] => {
+                    let id = hit.get("id").and_then(|id| id.as_str()).unwrap_or_default();
+                    eprintln!("Found {}", id);
+                    let data = get_resource_by_id(client, aliases, id).await?;
+                    show_resource(client, aliases, options, data, &filters).await
+                }
+                _ => {
+                    eprintln!("{} resources match '{}'; give the full name, or narrow it with --rg or --type", hits.len(), pattern);
+                    output.print(&Value::Array(hits.iter().map(find_candidate).collect()));
+                    Ok(())
+                }
+            }
+        }
+        Command::Kql | Command::Subscriptions => unreachable!("kql and subscriptions are handled before the target is resolved"),
+    }
+}
+
+/// print a fetched resource, with its references first when --expand-refs asks for them
+async fn show_resource(client: &ArmClient, aliases: &AliasTable, options: &Options, mut data: Value, filters: &[Filter]) -> Result<(), AzureSearchError> {
+    if let Some(depth) = options.expand_refs {
+        expand_references(&mut data, depth, options.concurrency, |id| async move {
+            get_resource_by_id(client, aliases, &id).await
+        }).await;
+    }
+    print_resource(&data, filters, &options.output)
+}
+
+/// the resources whose name matches: a glob when the pattern has `*` or `?`,
+/// otherwise exact, falling back to a prefix when nothing is named exactly that
+fn find_by_name(resources: &[Value], pattern: &str) -> Vec<Value> {
+    let name_of = |resource: &Value| resource.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_lowercase();
+    let named = |matches: &dyn Fn(&str) -> bool| -> Vec<Value> {
+        resources.iter().filter(|r| matches(&name_of(r))).cloned().collect()
+    };
+
+    let pattern = pattern.to_lowercase();
+    if pattern.contains(['*', '?']) {
+        return named(&|name| glob_matches(&pattern, name));
+    }
+    let exact = named(&|name| name == pattern);
+    if !exact.is_empty() {
+        return exact;
+    }
+    named(&|name| name.starts_with(&pattern))
+}
+
+/// one of several `find` hits as name, resource group, type and id; listings
+/// carry no resourceGroup, so it comes from the id
+fn find_candidate(resource: &Value) -> Value {
+    let mut candidate = serde_json::Map::new();
+    for column in ["name", "resourceGroup", "type", "id"] {
+        candidate.insert(column.to_string(), column_value(resource, column).unwrap_or(Value::Null));
+    }
+    Value::Object(candidate)
+}
+
+/// print one resource through its filters: searches and where: clauses decide
+/// whether it is shown at all, then subsearch: or a field path picks what is shown
+fn print_resource(data: &Value, filters: &[Filter], output: &Output) -> Result<(), AzureSearchError> {
+    let (narrowing, shaping) = split_filters(filters)?;
+    if !narrowing.iter().all(|f| f.keeps(data)) {
+        output.no_matches(&format!("No matches found for '{}'", describe_filters(&narrowing)));
+        return Ok(());
+    }
+
+    match shaping {
+        None => {
+            // no query - return the full resource
+            output.print(data);
+        }
+        Some(Filter::Subsearch(expr)) => {
+            // subsearch query on specific resource - returns just matching fields
+            let results = subsearch_json(data, expr);
+            if results.as_object().is_none_or(|obj| obj.is_empty()) {
+                output.no_matches(&format!("No matches found for '{}'", expr));
+            } else {
+                output.print_fields(&results);
+            }
+        }
+        Some(Filter::Field(field)) => {
+            // field query on specific resource, a plain or nested path
+            let value = get_field(data, field).ok_or_else(|| AzureSearchError::NotFound {
+                message: format!("Field '{}' not found", field),
+            })?;
+            output.print(&value);
+        }
+        Some(_) => unreachable!("split_filters only returns subsearch and field filters"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_field() {
+        let data = serde_json::json!({
             "name": "test-resource",
             "location": "eastus",
             "properties": {
@@ -628,17 +4344,1221 @@ mod tests {
             }
         });
 
-        let results = search_json(&data, "size");
+        let results = search_json(&data, &Matcher::substring("size").into());
         // the search should find the resource since it contains "size" fields
         assert!(!results.as_array().unwrap_or(&vec![]).is_empty() || results.is_object());
     }
 
     #[test]
     fn test_resource_mappings() {
-        let mappings = get_resource_mappings();
-        assert!(mappings.contains_key("network"));
-        assert!(mappings.contains_key("storage"));
-        assert!(mappings.contains_key("vm"));
-        assert_eq!(mappings.get("network").unwrap().0, "Microsoft.Network/virtualNetworks");
+        let mappings = AliasTable::builtin();
+        assert!(mappings.lookup("network").is_some());
+        assert!(mappings.lookup("storage").is_some());
+        assert!(mappings.lookup("vm").is_some());
+        assert_eq!(mappings.lookup("network").unwrap().provider_type, "Microsoft.Network/virtualNetworks");
+    }
+
+    #[test]
+    fn test_config_aliases_merge_over_builtin() {
+        let config = serde_json::json!({
+            "aliases": {
+                "aci": "Microsoft.ContainerInstance/containerGroups",
+                "pg": { "type": "Microsoft.DBforPostgreSQL/flexibleServers", "apiVersion": "2022-12-01", "category": "Database", "description": "PostgreSQL Flexible Servers" },
+                "vnet": "Microsoft.Network/virtualNetworks",
+                "sql": { "type": "Microsoft.Sql/servers/databases" },
+            },
+            "apiVersions": { "microsoft.compute/virtualmachines": "2024-03-01" },
+        });
+        let table = AliasTable::load(&config).unwrap();
+
+        let aci = table.lookup("ACI").unwrap();
+        assert_eq!(aci.category, "Custom");
+        assert!(aci.api_version.is_empty());
+        let pg = table.lookup("pg").unwrap();
+        assert_eq!((pg.category.as_str(), pg.api_version.as_str()), ("Database", "2022-12-01"));
+        assert_eq!(table.lookup("vm").unwrap().api_version, "2024-03-01");
+
+        // re-pointing an alias moves it off the built-in entry
+        assert_eq!(table.lookup("sql").unwrap().provider_type, "Microsoft.Sql/servers/databases");
+        assert_eq!(table.lookup("sqlserver").unwrap().provider_type, "Microsoft.Sql/servers");
+        assert_eq!(table.entries.iter().filter(|e| e.aliases.contains(&"vnet".to_string())).count(), 1);
+
+        // every alias in the listing resolves
+        for entry in &table.entries {
+            for alias in &entry.aliases {
+                assert_eq!(table.lookup(alias).unwrap().provider_type, entry.provider_type);
+            }
+        }
+
+        assert!(matches!(AliasTable::load(&serde_json::json!({ "aliases": { "x": "novalue" } })), Err(AzureSearchError::Config { .. })));
+        assert!(matches!(AliasTable::load(&serde_json::json!({ "apiVersions": { "Microsoft.Foo/bars": "1" } })), Err(AzureSearchError::Config { .. })));
+    }
+
+    #[test]
+    fn test_parse_cli_token() {
+        let data = serde_json::json!({
+            "accessToken": "abc",
+            "expiresOn": "2024-01-01 12:00:00.000000",
+            "expires_on": 1704110400
+        });
+        let token = parse_cli_token(&data).unwrap();
+        assert_eq!(token.token, "abc");
+        assert_eq!(token.expires_on, 1704110400);
+        assert!(!token.is_fresh());
+
+        // older CLI versions only report a local timestamp
+        let legacy = serde_json::json!({ "accessToken": "abc", "expiresOn": "2099-01-01 00:00:00.000000" });
+        assert!(parse_cli_token(&legacy).unwrap().is_fresh());
+        assert!(parse_cli_token(&serde_json::json!({ "expiresOn": "2099-01-01 00:00:00" })).is_err());
+    }
+
+    #[test]
+    fn test_token_cache_round_trip() {
+        let path = env::temp_dir().join(format!("azure_search_tokens_{}.json", std::process::id()));
+        let cache = TokenCache { path: path.clone() };
+        let token = AccessToken { token: "cached".to_string(), expires_on: unix_now() + 3600 };
+
+        let audience = &CloudProfile::default().audience;
+        let secret = |client_id: &str| Credential::ClientSecret {
+            tenant: "tenant-a".to_string(),
+            client_id: client_id.to_string(),
+            secret: "s".to_string(),
+        };
+        let cli = Credential::AzureCli { tenant: Some("tenant-a".to_string()) };
+
+        // a readable file left from an older version is replaced by an owner-only one
+        std::fs::write(&path, "{}").unwrap();
+        let key = TokenCache::key(Some("tenant-a"), audience, std::slice::from_ref(&cli));
+        cache.store(&key, &token).unwrap();
+        assert_eq!(cache.load(&key), Some(token));
+        assert!(!path.with_extension(format!("json.{}.tmp", std::process::id())).exists());
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+        assert!(cache.load(&TokenCache::key(None, audience, std::slice::from_ref(&cli))).is_none());
+
+        // a CLI token is not reused for a service principal, nor one client id's for another
+        assert!(cache.load(&TokenCache::key(Some("tenant-a"), audience, &[secret("app-1")])).is_none());
+        assert_ne!(
+            TokenCache::key(Some("tenant-a"), audience, &[secret("app-1")]),
+            TokenCache::key(Some("tenant-a"), audience, &[secret("app-2")])
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// read one HTTP request (headers plus content-length body) from a test socket
+    async fn read_http_request(socket: &mut tokio::net::TcpStream) -> String {
+        use tokio::io::AsyncReadExt;
+
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end].lines()
+                    .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                    .unwrap_or(0);
+                if request.len() >= header_end + 4 + content_length {
+                    break;
+                }
+            }
+            if n == 0 {
+                break;
+            }
+        }
+        String::from_utf8_lossy(&request).to_string()
+    }
+
+    /// status line, extra headers and body of one canned response
+    type CannedResponse<'a> = (&'a str, Vec<(&'a str, &'a str)>, &'a str);
+
+    /// serve canned HTTP responses on localhost, one per connection, and hand back the raw requests
+    async fn serve_sequence(responses: Vec<CannedResponse<'_>>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        serve_sequence_on(listener, responses)
+    }
+
+    /// serve_sequence on a listener bound beforehand, for responses that link back to the server
+    fn serve_sequence_on(listener: tokio::net::TcpListener, responses: Vec<CannedResponse<'_>>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::AsyncWriteExt;
+
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let responses: Vec<String> = responses.into_iter()
+            .map(|(status, headers, body)| {
+                let extra_headers: String = headers.iter().map(|(k, v)| format!("{}: {}\r\n", k, v)).collect();
+                format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                    status, body.len(), extra_headers, body
+                )
+            })
+            .collect();
+
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                requests.push(read_http_request(&mut socket).await);
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        (base_url, handle)
+    }
+
+    /// serve a single canned HTTP response on localhost and hand back the raw request
+    async fn serve_once(status: &str, body: &str) -> (String, tokio::task::JoinHandle<String>) {
+        serve_once_with_headers(status, &[], body).await
+    }
+
+    async fn serve_once_with_headers(status: &str, headers: &[(&str, &str)], body: &str) -> (String, tokio::task::JoinHandle<String>) {
+        let (base_url, requests) = serve_sequence(vec![(status, headers.to_vec(), body)]).await;
+        let handle = tokio::spawn(async move { requests.await.unwrap().remove(0) });
+        (base_url, handle)
+    }
+
+    /// client pointed at a mock ARM server, with a token already in memory so no credential runs
+    fn test_client(arm_endpoint: &str, retry: RetryPolicy) -> ArmClient {
+        ArmClient {
+            http: reqwest::Client::new(),
+            credentials: Vec::new(),
+            arm_endpoint: arm_endpoint.to_string(),
+            authority: DEFAULT_AUTHORITY.to_string(),
+            tenant: None,
+            audience: format!("{}/", arm_endpoint),
+            token_cache: None,
+            token: Mutex::new(Some(AccessToken { token: "test-token".to_string(), expires_on: unix_now() + 3600 })),
+            api_version_cache: None,
+            api_versions: Mutex::new(HashMap::new()),
+            retry,
+            verbose: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_all_pages_follows_next_link() {
+        // both pages come from one server, so page one's nextLink can point back at it
+        async fn serve_pages(next_host: Option<&str>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let base_url = format!("http://{}", listener.local_addr().unwrap());
+            let page_one = format!(
+                r#"{{"value":[{{"name":"a"}},{{"name":"b"}}],"nextLink":"{}/subscriptions/s/resources?$skiptoken=abc"}}"#,
+                next_host.unwrap_or(&base_url)
+            );
+            serve_sequence_on(listener, vec![
+                ("200 OK", vec![], page_one.as_str()),
+                ("200 OK", vec![], r#"{"value":[{"name":"c"},{"name":"d"}]}"#),
+            ])
+        }
+        let names = |paged: &PagedResult| paged.data["value"].as_array().unwrap().iter().map(|v| v["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+
+        let (base_url, requests) = serve_pages(None).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+        let paged = client.get_all_pages(&format!("{}/subscriptions/s/resources", base_url), PageLimit::default()).await.unwrap();
+        assert_eq!(names(&paged), ["a", "b", "c", "d"]);
+        assert_eq!(paged.pages, 2);
+        assert!(!paged.truncated);
+        assert!(requests.await.unwrap()[1].starts_with("GET /subscriptions/s/resources?$skiptoken=abc "));
+
+        // --max-pages 1 stops before following the link
+        let (base_url, _) = serve_pages(None).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+        let limited = client.get_all_pages(&base_url, PageLimit { max_pages: Some(1), max_items: None }).await.unwrap();
+        assert_eq!(names(&limited), ["a", "b"]);
+        assert_eq!(limited.pages, 1);
+        assert!(limited.truncated);
+
+        // --max-items 3 cuts the second page short
+        let (base_url, _) = serve_pages(None).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+        let limited = client.get_all_pages(&base_url, PageLimit { max_pages: None, max_items: Some(3) }).await.unwrap();
+        assert_eq!(names(&limited), ["a", "b", "c"]);
+        assert_eq!(limited.pages, 2);
+        assert!(limited.truncated);
+
+        // exactly max-items on the last page is not a truncation
+        let (base_url, _) = serve_pages(None).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+        let exact = client.get_all_pages(&base_url, PageLimit { max_pages: None, max_items: Some(4) }).await.unwrap();
+        assert_eq!(exact.pages, 2);
+        assert!(!exact.truncated);
+
+        // a nextLink to another host or port never gets the token
+        for foreign in ["https://attacker.example", "http://127.0.0.1:9"] {
+            let (base_url, _) = serve_pages(Some(foreign)).await;
+            let client = test_client(&base_url, RetryPolicy::default());
+            let result = client.get_all_pages(&base_url, PageLimit::default()).await;
+            assert!(matches!(result, Err(AzureSearchError::Usage { .. })), "{}", foreign);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_secret_flow_against_mock_endpoint() {
+        let (authority, request) = serve_once("200 OK", r#"{"token_type":"Bearer","expires_in":3599,"access_token":"mock-token"}"#).await;
+
+        let credential = Credential::ClientSecret {
+            tenant: "my-tenant".to_string(),
+            client_id: "my-client".to_string(),
+            secret: "s3cret".to_string(),
+        };
+        let token = credential.get_token(&reqwest::Client::new(), &authority, &CloudProfile::default().audience).await.unwrap();
+        assert_eq!(token.token, "mock-token");
+        assert!(token.is_fresh());
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /my-tenant/oauth2/v2.0/token "));
+        assert!(request.contains("grant_type=client_credentials"));
+        assert!(request.contains("client_secret=s3cret"));
+        assert!(request.contains("scope=https%3A%2F%2Fmanagement.azure.com%2F.default"));
+    }
+
+    #[tokio::test]
+    async fn test_client_secret_flow_reports_aad_error() {
+        let (authority, _request) = serve_once("401 Unauthorized", r#"{"error":"invalid_client","error_description":"AADSTS7000215: Invalid client secret"}"#).await;
+
+        let credential = Credential::ClientSecret {
+            tenant: "my-tenant".to_string(),
+            client_id: "my-client".to_string(),
+            secret: "wrong".to_string(),
+        };
+        let err = credential.get_token(&reqwest::Client::new(), &authority, &CloudProfile::default().audience).await.unwrap_err();
+        assert!(err.contains("AADSTS7000215"));
+    }
+
+    #[tokio::test]
+    async fn test_managed_identity_against_mock_imds() {
+        let (endpoint, request) = serve_once("200 OK", r#"{"access_token":"imds-token","expires_on":"4102444800","expires_in":"86399","token_type":"Bearer"}"#).await;
+
+        let credential = Credential::ManagedIdentity {
+            endpoint,
+            client_id: Some("user-assigned-id".to_string()),
+            probe: false,
+        };
+        let token = credential.get_token(&reqwest::Client::new(), DEFAULT_AUTHORITY, &CloudProfile::default().audience).await.unwrap();
+        assert_eq!(token, AccessToken { token: "imds-token".to_string(), expires_on: 4102444800 });
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("GET /metadata/identity/oauth2/token?api-version=2018-02-01&resource=https%3A%2F%2Fmanagement.azure.com%2F&client_id=user-assigned-id "));
+        assert!(request.to_lowercase().contains("metadata: true"));
+    }
+
+    #[test]
+    fn test_auto_chain_probes_imds_only_when_expected() {
+        let order = |imds_endpoint: &str, identity_client_id: Option<&str>| -> Vec<String> {
+            credential_chain(AuthMode::Auto, None, imds_endpoint, identity_client_id).unwrap()
+                .iter()
+                .filter_map(|c| match c {
+                    Credential::ManagedIdentity { probe: true, .. } => Some("msi probe".to_string()),
+                    Credential::ManagedIdentity { probe: false, .. } => Some("msi".to_string()),
+                    Credential::AzureCli { .. } => Some("cli".to_string()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(order(DEFAULT_IMDS_ENDPOINT, None), vec!["cli", "msi probe"]);
+        assert_eq!(order(DEFAULT_IMDS_ENDPOINT, Some("user-assigned-id")), vec!["msi", "cli"]);
+        assert_eq!(order("http://127.0.0.1:8080", None), vec!["msi", "cli"]);
+    }
+
+    #[tokio::test]
+    async fn test_workload_identity_sends_federated_token() {
+        let (authority, request) = serve_once("200 OK", r#"{"token_type":"Bearer","expires_in":3599,"access_token":"federated"}"#).await;
+        let token_file = env::temp_dir().join(format!("azure_search_federated_{}", std::process::id()));
+        std::fs::write(&token_file, "projected.jwt.value\n").unwrap();
+
+        let credential = Credential::WorkloadIdentity {
+            tenant: "my-tenant".to_string(),
+            client_id: "my-client".to_string(),
+            token_file: token_file.clone(),
+        };
+        let token = credential.get_token(&reqwest::Client::new(), &authority, &CloudProfile::default().audience).await.unwrap();
+        assert_eq!(token.token, "federated");
+
+        let request = request.await.unwrap();
+        assert!(request.contains("client_assertion=projected.jwt.value"));
+        assert!(request.contains("client_assertion_type=urn%3Aietf%3Aparams%3Aoauth%3Aclient-assertion-type%3Ajwt-bearer"));
+        std::fs::remove_file(token_file).unwrap();
+    }
+
+    #[test]
+    fn test_cloud_profile_resolution() {
+        let public = CloudProfile::resolve(None, None, None, None).unwrap();
+        assert_eq!(public.arm_endpoint, "https://management.azure.com");
+        assert_eq!(public.audience, "https://management.azure.com/");
+
+        let gov = CloudProfile::resolve(Some("AzureUSGovernment"), None, None, None).unwrap();
+        assert_eq!(gov.arm_endpoint, "https://management.usgovcloudapi.net");
+        assert_eq!(gov.authority, "https://login.microsoftonline.us");
+        assert_eq!(CloudProfile::resolve(Some("china"), None, None, None).unwrap().name, "AzureChina");
+
+        // a custom cloud pointing at a local mock ARM server
+        let mock = CloudProfile::resolve(Some("custom"), Some("http://127.0.0.1:8080/"), Some("http://127.0.0.1:8081"), None).unwrap();
+        assert_eq!(mock.arm_endpoint, "http://127.0.0.1:8080");
+        assert_eq!(mock.audience, "http://127.0.0.1:8080/");
+        assert_eq!(mock.authority, "http://127.0.0.1:8081");
+
+        assert!(CloudProfile::resolve(Some("custom"), None, None, None).is_err());
+        assert!(CloudProfile::resolve(Some("mars"), None, None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_arm_error_body_is_kept() {
+        let body = r#"{"error":{"code":"ResourceGroupNotFound","message":"Resource group 'rg-missing' could not be found."}}"#;
+        let (base_url, _request) = serve_once_with_headers("404 Not Found", &[("x-ms-correlation-request-id", "abc-123")], body).await;
+
+        let url = format!("{}/subscriptions/s/resourceGroups/rg-missing/resources", base_url);
+        let response = reqwest::get(&url).await.unwrap();
+        let err = read_arm_response(&url, response).await.unwrap_err();
+
+        assert_eq!(err.exit_code(), 5);
+        match &err {
+            AzureSearchError::Http { status, error, correlation_id, .. } => {
+                assert_eq!(*status, 404);
+                assert_eq!(error.as_ref().unwrap().code, "ResourceGroupNotFound");
+                assert_eq!(correlation_id.as_deref(), Some("abc-123"));
+            }
+            other => panic!("expected http error, got {:?}", other),
+        }
+        assert!(err.to_string().contains("ResourceGroupNotFound: Resource group 'rg-missing' could not be found."));
+    }
+
+    #[test]
+    fn test_error_exit_codes_are_distinct() {
+        let json_error = serde_json::from_str::<Value>("{").unwrap_err();
+        let errors = [
+            AzureSearchError::usage("bad"),
+            AzureSearchError::Config { message: "bad".to_string() },
+            AzureSearchError::Auth { message: "bad".to_string() },
+            AzureSearchError::Http { status: 500, url: "u".to_string(), error: None, correlation_id: None },
+            AzureSearchError::UnknownType { resource_type: "bad".to_string() },
+            AzureSearchError::Json { url: "u".to_string(), source: json_error },
+            AzureSearchError::NotFound { message: "bad".to_string() },
+        ];
+        let mut codes: Vec<i32> = errors.iter().map(|e| e.exit_code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0) && !codes.contains(&1));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(3, Some(Duration::from_secs(7))), Duration::from_secs(7));
+
+        for attempt in 1..10 {
+            let ceiling = policy.base_delay.saturating_mul(2u32.pow(attempt - 1)).min(policy.max_delay);
+            let delay = policy.delay(attempt, None);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "attempt {}: {:?}", attempt, delay);
+        }
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("retry-after", "12".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(12)));
+        headers.insert("x-ms-retry-after-ms", "250".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(250)));
+        assert_eq!(retry_after(&reqwest::header::HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_throttled_request_is_retried() {
+        let (base_url, requests) = serve_sequence(vec![
+            ("429 Too Many Requests", vec![("Retry-After", "0")], r#"{"error":{"code":"TooManyRequests","message":"slow down"}}"#),
+            ("503 Service Unavailable", vec![], "{}"),
+            ("200 OK", vec![], r#"{"value":[{"name":"vm1"}]}"#),
+        ]).await;
+
+        let policy = RetryPolicy { base_delay: Duration::from_millis(1), ..RetryPolicy::default() };
+        let client = test_client(&base_url, policy);
+        let data = client.get_json(&format!("{}/subscriptions/s/resources", base_url)).await.unwrap();
+
+        assert_eq!(data["value"][0]["name"], "vm1");
+        assert_eq!(requests.await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_request_times_out_at_deadline() {
+        // accept connections but never answer them
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let policy = RetryPolicy { deadline: Duration::from_millis(300), base_delay: Duration::from_millis(1), ..RetryPolicy::default() };
+        let client = test_client(&base_url, policy);
+        let started = Instant::now();
+        let err = client.get_json(&format!("{}/subscriptions/s/resources", base_url)).await.unwrap_err();
+
+        assert!(matches!(&err, AzureSearchError::Transport { source, .. } if source.is_timeout()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_max_attempts() {
+        let (base_url, _requests) = serve_sequence(vec![
+            ("503 Service Unavailable", vec![], "{}"),
+            ("503 Service Unavailable", vec![], r#"{"error":{"code":"ServerBusy","message":"try later"}}"#),
+        ]).await;
+
+        let policy = RetryPolicy { max_attempts: 2, base_delay: Duration::from_millis(1), ..RetryPolicy::default() };
+        let client = test_client(&base_url, policy);
+        let err = client.get_json(&format!("{}/subscriptions/s/resources", base_url)).await.unwrap_err();
+
+        assert!(err.to_string().contains("ServerBusy"));
+    }
+
+    #[tokio::test]
+    async fn test_resource_graph_follows_skip_token() {
+        let (base_url, requests) = serve_sequence(vec![
+            ("200 OK", vec![], r#"{"totalRecords":3,"count":2,"data":[{"name":"pip1"},{"name":"pip2"}],"$skipToken":"page-2"}"#),
+            ("200 OK", vec![], r#"{"totalRecords":3,"count":1,"data":[{"name":"pip3"}]}"#),
+        ]).await;
+
+        let client = test_client(&base_url, RetryPolicy::default());
+        let scope = GraphScope::Subscriptions(vec!["sub-a".to_string(), "sub-b".to_string()]);
+        let paged = query_resource_graph(&client, "Resources | where type =~ 'microsoft.network/publicipaddresses'", &scope, PageLimit::default()).await.unwrap();
+
+        assert_eq!(paged.pages, 2);
+        assert!(!paged.truncated);
+        assert_eq!(paged.data["value"].as_array().unwrap().len(), 3);
+        assert_eq!(search_json(&paged.data, &Matcher::substring("pip3").into()).as_array().unwrap().len(), 1);
+
+        let requests = requests.await.unwrap();
+        assert!(requests[0].starts_with("POST /providers/Microsoft.ResourceGraph/resources?api-version=2022-10-01 "));
+        assert!(requests[0].contains(r#""subscriptions":["sub-a","sub-b"]"#));
+        assert!(!requests[0].contains("$skipToken"));
+        assert!(requests[1].contains(r#""$skipToken":"page-2""#));
+    }
+
+    #[test]
+    fn test_merge_listings_tags_subscriptions() {
+        let subscriptions = vec![
+            SubscriptionRef { id: "sub-a".to_string(), display_name: Some("Production".to_string()) },
+            SubscriptionRef { id: "sub-b".to_string(), display_name: None },
+        ];
+        let listing = |names: &[&str]| PagedResult {
+            data: serde_json::json!({ "value": names.iter().map(|n| serde_json::json!({ "name": n })).collect::<Vec<_>>() }),
+            pages: 1,
+            truncated: false,
+        };
+
+        let merged = merge_listings(&subscriptions, vec![listing(&["vm1", "vm2"]), listing(&["vm3"])], PageLimit::default());
+        let values = merged.data["value"].as_array().unwrap();
+        assert_eq!(merged.pages, 2);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0]["subscriptionId"], "sub-a");
+        assert_eq!(values[0]["subscriptionDisplayName"], "Production");
+        assert_eq!(values[2]["subscriptionId"], "sub-b");
+        assert!(values[2]["subscriptionDisplayName"].is_null());
+
+        // a single subscription is left untouched, and the item limit applies across the merge
+        let single = merge_listings(&subscriptions[..1], vec![listing(&["vm1"])], PageLimit::default());
+        assert!(single.data["value"][0].get("subscriptionId").is_none());
+        let limited = merge_listings(&subscriptions, vec![listing(&["vm1", "vm2"]), listing(&["vm3"])], PageLimit { max_pages: None, max_items: Some(2) });
+        assert_eq!(limited.data["value"].as_array().unwrap().len(), 2);
+        assert!(limited.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_all_subscriptions() {
+        let (base_url, _requests) = serve_sequence(vec![
+            ("200 OK", vec![], r#"{"value":[{"subscriptionId":"sub-a","displayName":"Production","state":"Enabled"},{"subscriptionId":"sub-b","displayName":"Dev","state":"Enabled"}]}"#),
+        ]).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+
+        let all = resolve_subscriptions(&client, "*").await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1], SubscriptionRef { id: "sub-b".to_string(), display_name: Some("Dev".to_string()) });
+
+        // a single explicit subscription id does not need a lookup
+        let id = "0b1f6471-1bf0-4dda-aec3-cb9272f09590";
+        let one = resolve_subscriptions(&client, id).await.unwrap();
+        assert_eq!(one, vec![SubscriptionRef { id: id.to_string(), display_name: None }]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_subscription_names() {
+        let listing = r#"{"value":[{"subscriptionId":"sub-a","displayName":"Production","state":"Enabled","tenantId":"t1"},{"subscriptionId":"sub-b","displayName":"Dev","state":"Disabled","tenantId":"t1"}]}"#;
+        let (base_url, _requests) = serve_sequence(vec![
+            ("200 OK", vec![], listing),
+            ("200 OK", vec![], listing),
+            ("200 OK", vec![], listing),
+        ]).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+
+        let named = resolve_subscriptions(&client, "dev").await.unwrap();
+        assert_eq!(named, vec![SubscriptionRef { id: "sub-b".to_string(), display_name: Some("Dev".to_string()) }]);
+        let mixed = resolve_subscriptions(&client, "Production,sub-b").await.unwrap();
+        assert_eq!(mixed.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), ["sub-a", "sub-b"]);
+        let missing = resolve_subscriptions(&client, "Staging").await.unwrap_err();
+        assert!(matches!(missing, AzureSearchError::NotFound { .. }));
+
+        let rows = subscription_rows(&serde_json::from_str(listing).unwrap());
+        assert_eq!(rows["value"][1], serde_json::json!({ "name": "Dev", "subscriptionId": "sub-b", "state": "Disabled", "tenantId": "t1" }));
+        assert!(is_guid("0B1F6471-1BF0-4DDA-AEC3-CB9272F09590"));
+        assert!(!is_guid("Production"));
+    }
+
+    #[test]
+    fn test_pick_api_version_prefers_stable() {
+        assert_eq!(pick_api_version(&["2021-03-01", "2023-05-01", "2024-01-01-preview"]), Some("2023-05-01".to_string()));
+        assert_eq!(pick_api_version(&["2022-01-01-preview", "2023-02-01-preview"]), Some("2023-02-01-preview".to_string()));
+        assert_eq!(pick_api_version(&[]), None);
+
+        let provider = serde_json::json!({ "resourceTypes": [
+            { "resourceType": "containerGroups", "apiVersions": ["2023-05-01", "2021-10-01"] },
+            { "resourceType": "locations", "apiVersions": [] },
+        ]});
+        let versions = provider_api_versions("Microsoft.ContainerInstance", &provider);
+        assert_eq!(versions.get("microsoft.containerinstance/containergroups"), Some(&"2023-05-01".to_string()));
+        assert_eq!(versions.len(), 1);
+    }
+
+    #[test]
+    fn test_resolved_type_paths() {
+        let subnets = ResolvedType::parse("Microsoft.Network/virtualNetworks/subnets", "2023-04-01").unwrap();
+        assert!(subnets.is_child());
+        assert_eq!(subnets.path(&["vnet1", "default"]).unwrap(), "Microsoft.Network/virtualNetworks/vnet1/subnets/default");
+        assert_eq!(subnets.path(&["vnet1"]).unwrap(), "Microsoft.Network/virtualNetworks/vnet1/subnets");
+        assert!(matches!(subnets.path(&["a", "b", "c"]), Err(AzureSearchError::Usage { .. })));
+
+        let groups = ResolvedType::parse("Microsoft.ContainerInstance/containerGroups", "").unwrap();
+        assert_eq!(groups.full_name(), "Microsoft.ContainerInstance/containerGroups");
+        assert!(!groups.is_child());
+
+        assert!(ResolvedType::parse("vnet", "").is_none());
+        assert!(ResolvedType::parse("Microsoft.Network/", "").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_alias_resolves_without_registry_lookup() {
+        // nothing listens here, so any registry call would fail
+        let client = test_client("http://127.0.0.1:9", RetryPolicy::default());
+        let aliases = AliasTable::builtin();
+        let resolved = resolve_resource_type(&client, &aliases, "VNet").await.unwrap();
+        assert_eq!(resolved.full_name(), "Microsoft.Network/virtualNetworks");
+        assert_eq!(resolved.api_version, "2023-05-01");
+        assert!(matches!(resolve_resource_type(&client, &aliases, "nonsense").await, Err(AzureSearchError::UnknownType { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_provider_lookup_is_shared_per_namespace() {
+        let provider = r#"{"resourceTypes":[
+            {"resourceType":"containerGroups","apiVersions":["2023-05-01"]},
+            {"resourceType":"containerGroups/containers","apiVersions":["2022-09-01"]}
+        ]}"#;
+        let (base_url, requests) = serve_sequence(vec![("200 OK", vec![], provider)]).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+        let aliases = AliasTable::builtin();
+
+        let resolved = futures::future::join_all([
+            "Microsoft.ContainerInstance/containerGroups",
+            "microsoft.containerinstance/containerGroups",
+            "Microsoft.ContainerInstance/containerGroups/containers",
+        ].map(|t| resolve_resource_type(&client, &aliases, t))).await;
+        let versions: Vec<String> = resolved.into_iter().map(|r| r.unwrap().api_version).collect();
+        assert_eq!(versions, vec!["2023-05-01", "2023-05-01", "2022-09-01"]);
+        assert!(matches!(
+            resolve_resource_type(&client, &aliases, "Microsoft.ContainerInstance/missing").await,
+            Err(AzureSearchError::UnknownType { .. })
+        ));
+        assert_eq!(requests.await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unlisted_child_type_uses_parent_api_version() {
+        let provider = r#"{"resourceTypes":[
+            {"resourceType":"networkInterfaces","apiVersions":["2023-05-01","2024-01-01-preview"]}
+        ]}"#;
+        let (base_url, requests) = serve_sequence(vec![("200 OK", vec![], provider)]).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+        let aliases = AliasTable::builtin();
+
+        let resolved = resolve_resource_type(&client, &aliases, "Microsoft.Network/networkInterfaces/ipConfigurations").await.unwrap();
+        assert_eq!(resolved.full_name(), "Microsoft.Network/networkInterfaces/ipConfigurations");
+        assert_eq!(resolved.api_version, "2023-05-01");
+        // no parent in the registry either
+        assert!(matches!(
+            resolve_resource_type(&client, &aliases, "Microsoft.Network/loadBalancers/backendAddressPools").await,
+            Err(AzureSearchError::UnknownType { .. })
+        ));
+        assert_eq!(requests.await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_api_version_cache_replaces_file() {
+        let dir = env::temp_dir().join(format!("azure_search_api_versions_{}", std::process::id()));
+        let cache = ApiVersionCache { path: dir.join("api-versions.json") };
+        let endpoint = "https://management.azure.com";
+        cache.store(endpoint, &HashMap::from([("microsoft.network/virtualnetworks".to_string(), "2023-05-01".to_string())])).unwrap();
+        cache.store(endpoint, &HashMap::from([("microsoft.web/sites".to_string(), "2023-01-01".to_string())])).unwrap();
+
+        assert_eq!(cache.load("https://management.azure.com|microsoft.network/virtualnetworks"), Some("2023-05-01".to_string()));
+        assert_eq!(cache.load("https://management.azure.com|microsoft.web/sites"), Some("2023-01-01".to_string()));
+        // only the cache itself is left behind, no temp files
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let listing = serde_json::json!({ "value": [
+            { "id": "/subscriptions/s/resourceGroups/rg-a/providers/Microsoft.Compute/virtualMachines/vm1", "name": "vm1", "type": "Microsoft.Compute/virtualMachines", "location": "eastus", "tags": { "env": "prod", "team": "core" } },
+            { "id": "/subscriptions/s/resourceGroups/rg-b/providers/Microsoft.Compute/virtualMachines/vm,2", "name": "vm,2", "type": "Microsoft.Compute/virtualMachines", "location": "westus" },
+        ]});
+        let output = |format, columns: Option<&[&str]>| Output { format, columns: columns.map(|c| c.iter().map(|s| s.to_string()).collect()) };
+
+        let table = output(OutputFormat::Table, None).render(&listing);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0].split_whitespace().collect::<Vec<_>>(), DEFAULT_COLUMNS);
+        assert!(lines[2].starts_with("vm1 "));
+        assert!(lines[2].contains("rg-a") && lines[2].ends_with("env=prod; team=core"));
+        assert_eq!(lines.len(), 4);
+
+        let csv = output(OutputFormat::Csv, Some(&["name", "resourceGroup"])).render(&listing);
+        assert_eq!(csv, "name,resourceGroup\nvm1,rg-a\n\"vm,2\",rg-b");
+
+        let ndjson = output(OutputFormat::Ndjson, None).render(&listing);
+        assert_eq!(ndjson.lines().count(), 2);
+        assert_eq!(serde_json::from_str::<Value>(ndjson.lines().next().unwrap()).unwrap()["name"], "vm1");
+
+        let projected = output(OutputFormat::Json, Some(&["name", "tags.env"])).render(&listing);
+        assert_eq!(serde_json::from_str::<Value>(&projected).unwrap(), serde_json::json!([
+            { "name": "vm1", "tags.env": "prod" },
+            { "name": "vm,2", "tags.env": null },
+        ]));
+
+        let yaml = output(OutputFormat::Yaml, None).render(&listing["value"][1]);
+        assert!(yaml.contains("location: westus"));
+
+        // field values that are not resources get their own columns
+        let scalar = output(OutputFormat::Csv, None).render(&serde_json::json!("10.0.0.0/16"));
+        assert_eq!(scalar, "value\n10.0.0.0/16");
+        assert!(matches!(OutputFormat::parse("xml"), Err(AzureSearchError::Usage { .. })));
+    }
+
+    #[test]
+    fn test_nested_field_paths() {
+        let vnet = serde_json::json!({
+            "name": "vnet1",
+            "properties": {
+                "addressSpace": { "addressPrefixes": ["10.0.0.0/16"] },
+                "subnets": [
+                    { "name": "default", "properties": { "addressPrefix": "10.0.0.0/24" } },
+                    { "name": "aks", "properties": { "addressPrefix": "10.0.1.0/24" } },
+                ],
+            },
+            "tags": { "cost.center": "42" },
+        });
+
+        assert_eq!(get_field(&vnet, "properties.subnets[0].properties.addressPrefix").unwrap(), "10.0.0.0/24");
+        assert_eq!(get_field(&vnet, "properties.subnets[-1].name").unwrap(), "aks");
+        assert_eq!(get_field(&vnet, "Properties.AddressSpace.addressPrefixes[0]").unwrap(), "10.0.0.0/16");
+        assert_eq!(get_field(&vnet, "properties.subnets[*].name").unwrap(), serde_json::json!(["default", "aks"]));
+        assert_eq!(get_field(&vnet, "properties.subnets.*.properties.addressPrefix").unwrap(), serde_json::json!(["10.0.0.0/24", "10.0.1.0/24"]));
+        assert_eq!(get_field(&vnet, "tags[\"cost.center\"]").unwrap(), "42");
+        assert!(get_field(&vnet, "properties.subnets[5]").is_none());
+        assert!(get_field(&vnet, "properties.missing[*]").is_none());
+
+        for invalid in ["", "a..b", "a[0", "a[x]", "a[0]b", "a."] {
+            assert!(parse_field_path(invalid).is_err(), "{}", invalid);
+        }
+
+        let listing = serde_json::json!({ "value": [vnet, { "name": "bare" }] });
+        assert_eq!(project_field(&listing, "properties.subnets[0].name"), vec![
+            serde_json::json!({ "name": "vnet1", "properties.subnets[0].name": "default" }),
+        ]);
+    }
+
+    #[test]
+    fn test_regex_and_exact_modes() {
+        let nic = serde_json::json!({ "value": [
+            { "name": "nic1", "properties": { "privateIPAddress": "10.0.1.4" } },
+            { "name": "nic2", "properties": { "privateIPAddress": "10.0.10.4" } },
+        ]});
+        let names = |results: Value| results.as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+
+        // substring search cannot tell these apart, exact and anchored regex can
+        assert_eq!(names(search_json(&nic, &Matcher::substring("10.0.1").into())), ["nic1", "nic2"]);
+        assert_eq!(names(search_json(&nic, &Matcher::exact("10.0.1.4").into())), ["nic1"]);
+        assert_eq!(names(search_json(&nic, &Matcher::regex(r"^10\.0\.1\.").unwrap().into())), ["nic1"]);
+        assert_eq!(names(search_json(&nic, &Matcher::regex("PRIVATEIP").unwrap().into())), ["nic1", "nic2"]);
+
+        let fields = subsearch_json(&nic, &Matcher::regex(r"\.10\.").unwrap().into());
+        assert_eq!(fields, serde_json::json!({ "nic2.properties.privateIPAddress": "10.0.10.4" }));
+
+        // keys-only and values-only matching
+        let keys_only = Matcher::substring("nic").with_target(MatchTarget::Keys);
+        assert!(search_json(&nic, &keys_only.clone().into()).as_array().unwrap().is_empty());
+        let values_only = Matcher::substring("address").with_target(MatchTarget::Values);
+        assert!(search_json(&nic, &values_only.clone().into()).as_array().unwrap().is_empty());
+        assert_eq!(names(search_json(&nic, &Matcher::substring("address").with_target(MatchTarget::Keys).into())), ["nic1", "nic2"]);
+
+        assert!(matches!(Filter::parse("regex:(", MatchTarget::Both), Err(AzureSearchError::Usage { .. })));
+        assert!(matches!(Filter::parse("exact:x", MatchTarget::Both), Ok(Filter::Search(_))));
+        assert!(matches!(Filter::parse("subregex:x", MatchTarget::Both), Ok(Filter::Subsearch(_))));
+        assert!(is_filter("regex:x") && !is_filter("myRG"));
+    }
+
+    #[test]
+    fn test_typed_search() {
+        let nsg = serde_json::json!({
+            "name": "nsg1",
+            "properties": {
+                "securityRules": [
+                    { "name": "ssh", "properties": { "priority": 100, "destinationPortRange": "22", "enabled": true } },
+                    { "name": "app", "properties": { "priority": 200, "destinationPortRange": "8006" } },
+                ],
+                "addressPrefixes": ["10.0.0.0/16", "10.1.0.0/16"],
+                "ports": [80, 443],
+            },
+        });
+        let search = |term: &str| Matcher::search(term).unwrap();
+
+        // numbers, booleans and numeric strings all match as text
+        assert!(resource_contains_term(&nsg, &search("8006")));
+        assert!(resource_contains_term(&nsg, &search("true")));
+        assert!(resource_contains_term(&nsg, &search("443")));
+        assert!(!resource_contains_term(&nsg, &search("9999")));
+
+        // array elements are reported under indexed paths
+        let fields = subsearch_json(&nsg, &search("10.1.").into());
+        assert_eq!(fields, serde_json::json!({ "resource.properties.addressPrefixes[1]": "10.1.0.0/16" }));
+        let fields = subsearch_json(&nsg, &search("priority").into());
+        assert_eq!(fields.as_object().unwrap().len(), 2);
+        assert_eq!(fields["resource.properties.securityRules[1].properties.priority"], 200);
+
+        // numeric comparisons, with the key of the containing array for elements
+        let fields = subsearch_json(&nsg, &search("port>=1024").into());
+        assert_eq!(fields, serde_json::json!({ "resource.properties.securityRules[1].properties.destinationPortRange": "8006" }));
+        let fields = subsearch_json(&nsg, &search("ports>100").into());
+        assert_eq!(fields, serde_json::json!({ "resource.properties.ports[1]": 443 }));
+        assert!(resource_contains_term(&nsg, &search("priority<=100")));
+        assert!(!resource_contains_term(&nsg, &search("priority>500")));
+        assert!(resource_contains_term(&nsg, &search(">8000")));
+
+        assert_eq!(parse_comparison("port >= 1024"), Ok(Some(("port".to_string(), CompareOp::GreaterOrEqual, 1024.0))));
+        assert_eq!(parse_comparison("env=prod"), Ok(None));
+        assert_eq!(parse_comparison("a b<3"), Ok(None));
+        // not finite, so these are plain text
+        assert_eq!(parse_comparison("port=nan"), Ok(None));
+        assert_eq!(parse_comparison("port>=inf"), Ok(None));
+        assert!(!resource_contains_term(&serde_json::json!({ "port": "NaN" }), &search(">0")));
+        // a path never matches a single key, so it is refused
+        assert!(parse_comparison("properties.port>=1024").is_err());
+        assert!(matches!(SearchExpr::parse("properties.port>=1024", MatchTarget::Both), Err(AzureSearchError::Usage { .. })));
+        assert_eq!(parse_comparison("tls.version"), Ok(None));
+    }
+
+    #[test]
+    fn test_boolean_search_expressions() {
+        let listing = serde_json::json!({ "value": [
+            { "name": "web-linux", "properties": { "osType": "Linux", "access": "Allow", "port": 53 } },
+            { "name": "test-linux", "properties": { "osType": "Linux", "access": "Deny" } },
+            { "name": "win", "properties": { "osType": "Windows", "note": "not found" } },
+        ]});
+        let names = |expr: &str| {
+            let expr = SearchExpr::parse(expr, MatchTarget::Both).unwrap();
+            search_json(&listing, &expr).as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("linux AND NOT test"), ["web-linux"]);
+        assert_eq!(names("linux NOT test"), ["web-linux"]);
+        assert_eq!(names("53 AND Allow"), ["web-linux"]);
+        assert_eq!(names("windows OR deny"), ["test-linux", "win"]);
+        assert_eq!(names("NOT (windows OR deny)"), ["web-linux"]);
+        assert_eq!(names("web AND allow OR win"), ["web-linux", "win"]);
+
+        // without operators a term is still one phrase, and quotes keep keywords literal
+        assert_eq!(names("not found"), ["win"]);
+        assert_eq!(names("\"not found\" OR deny"), ["test-linux", "win"]);
+
+        // subsearch reports the positive terms' fields of matching resources only
+        let expr = SearchExpr::parse("linux AND allow AND NOT test", MatchTarget::Both).unwrap();
+        assert_eq!(subsearch_json(&listing, &expr), serde_json::json!({
+            "web-linux.name": "web-linux",
+            "web-linux.properties.osType": "Linux",
+            "web-linux.properties.access": "Allow",
+        }));
+
+        // parentheses without operators are part of the text
+        let calls = serde_json::json!({ "value": [
+            { "name": "q1", "properties": { "query": "Resources | summarize count() by type" } },
+            { "name": "q2", "properties": { "query": "foo(bar)" } },
+        ]});
+        let matching = |expr: &str| {
+            let expr = SearchExpr::parse(expr, MatchTarget::Both).unwrap();
+            search_json(&calls, &expr).as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(matching("count()"), ["q1"]);
+        assert_eq!(matching("foo(bar)"), ["q2"]);
+        assert_eq!(matching("a)"), Vec::<String>::new());
+        assert_eq!(matching("\"count()\" OR \"foo(bar)\""), ["q1", "q2"]);
+        assert_eq!(matching("( foo OR summarize )"), ["q1", "q2"]);
+
+        // compound operands are parenthesized when the expression is shown back
+        let shown = |expr: &str| SearchExpr::parse(expr, MatchTarget::Both).unwrap().to_string();
+        assert_eq!(shown("NOT (a AND b)"), "NOT (a AND b)");
+        assert_eq!(shown("(a OR b) AND NOT c"), "(a OR b) AND NOT c");
+        assert_eq!(shown("a AND b OR \"c d\""), "(a AND b) OR \"c d\"");
+
+        for invalid in ["a AND", "(a OR b", "(a) OR b)", "OR a", "\"open", "( a"] {
+            assert!(SearchExpr::parse(invalid, MatchTarget::Both).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_where_predicates() {
+        let nics = serde_json::json!({ "value": [
+            { "name": "nic1", "location": "eastus", "sku": { "name": "Standard_LRS" },
+              "properties": { "provisioningState": "Succeeded", "priority": 100,
+                "ipConfigurations": [{ "properties": { "privateIPAddress": "10.0.1.4" } }] } },
+            { "name": "nic2", "location": "westus", "sku": { "name": "Premium_LRS" },
+              "properties": { "provisioningState": "Failed", "priority": 300,
+                "ipConfigurations": [{ "properties": { "privateIPAddress": "192.168.0.5" } }] } },
+            { "name": "nic3", "location": "EastUS", "tags": { "env": "prod", "Größe": "L" },
+              "properties": { "provisioningState": "Succeeded", "addressPrefix": "10.0.8.0/24" } },
+        ]});
+        let names = |clauses: &[&str]| {
+            let filters: Vec<Predicate> = clauses.iter().map(|c| Predicate::parse(c).unwrap()).collect();
+            nics["value"].as_array().unwrap().iter()
+                .filter(|r| filters.iter().all(|p| p.matches(r)))
+                .map(|r| r["name"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(&["location=eastus"]), ["nic1", "nic3"]);
+        assert_eq!(names(&["properties.provisioningState!=Succeeded"]), ["nic2"]);
+        assert_eq!(names(&["sku.name~standard"]), ["nic1"]);
+        assert_eq!(names(&["sku.name!~standard"]), ["nic2"]);
+        assert_eq!(names(&["tags.env!=dev"]), ["nic3"]);
+        assert_eq!(names(&["properties.priority>100"]), ["nic2"]);
+        assert_eq!(names(&["properties.priority <= 100"]), ["nic1"]);
+        assert_eq!(names(&["tags.env exists"]), ["nic3"]);
+        assert_eq!(names(&["tags !exists"]), ["nic1", "nic2"]);
+        assert_eq!(names(&["tags.Größe=l"]), ["nic3"]);
+        assert_eq!(names(&["tags.Größe in 10.0.0.0/8"]), Vec::<String>::new());
+        assert_eq!(names(&["properties.ipConfigurations[*].properties.privateIPAddress in 10.0.0.0/16"]), ["nic1"]);
+        assert_eq!(names(&["properties.addressPrefix in 10.0.0.0/16"]), ["nic3"]);
+        assert_eq!(names(&["location=eastus", "properties.provisioningState=Succeeded", "sku !exists"]), ["nic3"]);
+
+        assert!(cidr_contains(parse_cidr("0.0.0.0/0").unwrap(), parse_cidr("8.8.8.8").unwrap()));
+        assert!(cidr_contains(parse_cidr("fd00::/8").unwrap(), parse_cidr("fd12::1").unwrap()));
+        assert!(!cidr_contains(parse_cidr("10.0.0.0/24").unwrap(), parse_cidr("10.0.0.0/16").unwrap()));
+        let bare = serde_json::json!({ "name": "listed", "type": "Microsoft.Compute/virtualMachines" });
+        assert!(!Predicate::parse("properties.provisioningState!=Succeeded").unwrap().matches(&bare));
+        assert!(!Predicate::parse("properties.provisioningState!~Succ").unwrap().matches(&bare));
+        assert!(Predicate::parse("location").is_err());
+        assert!(Predicate::parse("ip in nonsense").is_err());
+    }
+
+    #[test]
+    fn test_filters_combine() {
+        let listing = serde_json::json!({ "value": [
+            { "name": "vm1", "location": "eastus", "properties": { "osType": "Linux" } },
+            { "name": "vm2", "location": "westus", "properties": { "osType": "Linux" } },
+            { "name": "vm3", "location": "eastus", "properties": { "osType": "Windows" } },
+        ]});
+        let parse = |args: &[&str]| args.iter().map(|a| Filter::parse(a, MatchTarget::Both).unwrap()).collect::<Vec<_>>();
+
+        let filters = parse(&["where:location=eastus", "search:linux", "field:name"]);
+        let (narrowing, shaping) = split_filters(&filters).unwrap();
+        assert_eq!(narrowing.len(), 2);
+        assert!(matches!(shaping, Some(Filter::Field(_))));
+        let kept: Vec<&Value> = listing["value"].as_array().unwrap().iter().filter(|r| narrowing.iter().all(|f| f.keeps(r))).collect();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0]["name"], "vm1");
+        assert_eq!(describe_filters(&narrowing), "location=eastus AND linux");
+
+        assert!(split_filters(&parse(&["field:name", "subsearch:x"])).is_err());
+    }
+
+    #[test]
+    fn test_tag_filters_and_inventory() {
+        let resources = vec![
+            serde_json::json!({ "name": "dns1", "tags": { "Environment": "prod-east", "ManagedBy": "Terraform", "Project": "CoreDNS-Challenge" } }),
+            serde_json::json!({ "name": "dns2", "tags": { "environment": "Prod-West", "ManagedBy": "Terraform" } }),
+            serde_json::json!({ "name": "manual", "tags": { "Environment": "dev" } }),
+            serde_json::json!({ "name": "untagged" }),
+        ];
+        let names = |filter: &TagFilter| resources.iter().filter(|r| filter.matches(r)).map(|r| r["name"].as_str().unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(names(&TagFilter::parse("Environment=prod*").unwrap()), ["dns1", "dns2"]);
+        assert_eq!(names(&TagFilter::parse("environment=*-west").unwrap()), ["dns2"]);
+        assert_eq!(names(&TagFilter::parse("Project").unwrap()), ["dns1"]);
+        assert_eq!(names(&TagFilter::parse("Man*=terraform").unwrap()), ["dns1", "dns2"]);
+        assert_eq!(names(&TagFilter { missing: true, ..TagFilter::parse("ManagedBy").unwrap() }), ["manual", "untagged"]);
+        assert!(TagFilter::parse("=x").is_err());
+
+        assert!(glob_matches("a*c?e", "abbbcde"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("a*b", "acbc"));
+
+        let inventory = tag_inventory(&resources);
+        assert_eq!(inventory, serde_json::json!([
+            { "key": "Environment", "value": "dev", "resources": 1 },
+            { "key": "Environment", "value": "prod-east", "resources": 1 },
+            { "key": "Environment", "value": "Prod-West", "resources": 1 },
+            { "key": "ManagedBy", "value": "Terraform", "resources": 2 },
+            { "key": "Project", "value": "CoreDNS-Challenge", "resources": 1 },
+        ]));
+    }
+
+    #[test]
+    fn test_type_summary() {
+        let resources = vec![
+            serde_json::json!({ "type": "Microsoft.Storage/storageAccounts", "location": "eastus", "sku": { "name": "Standard_LRS" } }),
+            serde_json::json!({ "type": "Microsoft.Storage/storageAccounts", "location": "eastus", "sku": { "name": "Standard_LRS" } }),
+            serde_json::json!({ "type": "microsoft.storage/storageaccounts", "location": "westus", "sku": { "name": "Premium_LRS" } }),
+            serde_json::json!({ "type": "Microsoft.ContainerInstance/containerGroups", "location": "eastus" }),
+        ];
+        let summary = type_summary(&resources, &AliasTable::builtin());
+        assert_eq!(summary, serde_json::json!([
+            { "type": "Microsoft.ContainerInstance/containerGroups", "alias": "", "location": "eastus", "sku": "", "resources": 1, "typeTotal": 1 },
+            { "type": "Microsoft.Storage/storageAccounts", "alias": "storage", "location": "eastus", "sku": "Standard_LRS", "resources": 2, "typeTotal": 3 },
+            { "type": "Microsoft.Storage/storageAccounts", "alias": "storage", "location": "westus", "sku": "Premium_LRS", "resources": 1, "typeTotal": 3 },
+        ]));
+    }
+
+    #[test]
+    fn test_legacy_arguments_translate() {
+        let legacy = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            Invocation::from_legacy(&args, Target::default(), |a| a == "vms" || a == "network" || a.contains('/'))
+                .map(|invocation| invocation.command_line())
+        };
+
+        assert_eq!(legacy(&["12345"]).unwrap(), "list --sub 12345");
+        assert_eq!(legacy(&["12345", "all", "search:linux"]).unwrap(), "list --sub 12345 search:linux");
+        assert_eq!(legacy(&["12345", "network"]).unwrap(), "list --sub 12345 --type network");
+        assert_eq!(legacy(&["12345", "myRG"]).unwrap(), "list --sub 12345 --rg myRG");
+        assert_eq!(legacy(&["12345", "types"]).unwrap(), "types --sub 12345");
+        assert_eq!(legacy(&["12345", "myRG", "tags"]).unwrap(), "tags --sub 12345 --rg myRG");
+        assert_eq!(legacy(&["12345", "network", "vms"]).unwrap(), "list --sub 12345 --rg network --type vms");
+        assert_eq!(
+            legacy(&["12345", "myRG", "vms", "vm1", "properties.hardwareProfile.vmSize"]).unwrap(),
+            "get --sub 12345 --rg myRG --type vms --name vm1 properties.hardwareProfile.vmSize"
+        );
+        assert_eq!(
+            legacy(&["12345", "vms", "search:linux AND NOT test"]).unwrap(),
+            "list --sub 12345 --type vms 'search:linux AND NOT test'"
+        );
+        assert_eq!(
+            legacy(&["12345", "graph:Resources | where name == 'x'", "search:a"]).unwrap(),
+            r#"kql --sub 12345 --query 'Resources | where name == '\''x'\''' search:a"#
+        );
+        assert!(legacy(&["12345", "myRG", "vms", "vm1", "name", "extra"]).is_err());
+
+        assert_eq!(Command::parse("groups"), Some(Command::Groups));
+        assert_eq!(Command::parse("12345"), None);
+        assert_eq!(Command::Search.name(), "search");
+    }
+
+    #[test]
+    fn test_group_summary_counts_resources() {
+        let groups = vec![
+            serde_json::json!({ "id": "/subscriptions/s/resourceGroups/rg-coredns-challenge", "name": "rg-coredns-challenge", "location": "eastus", "properties": { "provisioningState": "Succeeded" }, "tags": { "Project": "CoreDNS-Challenge" } }),
+            serde_json::json!({ "id": "/subscriptions/s/resourceGroups/empty", "name": "empty", "location": "westus", "properties": { "provisioningState": "Deleting" } }),
+        ];
+        let resources = vec![
+            serde_json::json!({ "id": "/subscriptions/S/resourceGroups/RG-CoreDNS-Challenge/providers/Microsoft.Network/loadBalancers/lb" }),
+            serde_json::json!({ "id": "/subscriptions/s/resourceGroups/rg-coredns-challenge/providers/Microsoft.ContainerInstance/containerGroups/dns1" }),
+            serde_json::json!({ "id": "/subscriptions/other/resourceGroups/empty/providers/Microsoft.Network/publicIPAddresses/pip" }),
+        ];
+
+        let summary = group_summary(&groups, Some(&resources));
+        assert_eq!(summary["value"], serde_json::json!([
+            { "name": "rg-coredns-challenge", "location": "eastus", "provisioningState": "Succeeded", "resources": 2, "tags": { "Project": "CoreDNS-Challenge" } },
+            { "name": "empty", "location": "westus", "provisioningState": "Deleting", "resources": 0, "tags": {} },
+        ]));
+        // without --counts no resources are listed and there is no count column
+        assert!(group_summary(&groups, None)["value"][0].get("resources").is_none());
+        assert_eq!(id_scope("/subscriptions/s/resourcegroups/x"), Some(("s".to_string(), "x".to_string())));
+        assert_eq!(id_scope("/subscriptions/s"), None);
+    }
+
+    #[test]
+    fn test_resource_id_parse_and_format() {
+        let text = "/subscriptions/s1/resourceGroups/rg-coredns-challenge/providers/Microsoft.Network/networkInterfaces/nic1/ipConfigurations/ipconfig1";
+        let id = ResourceId::parse(text).unwrap();
+        assert_eq!(id.subscription, "s1");
+        assert_eq!(id.resource_group.as_deref(), Some("rg-coredns-challenge"));
+        assert_eq!(id.resource_type().as_deref(), Some("Microsoft.Network/networkInterfaces/ipConfigurations"));
+        assert_eq!(id.name().as_deref(), Some("nic1/ipconfig1"));
+        assert_eq!(id.to_string(), text);
+
+        // keywords are matched without case and written back canonically
+        let group = ResourceId::parse("/SUBSCRIPTIONS/s1/resourcegroups/rg/").unwrap();
+        assert_eq!(group.to_string(), "/subscriptions/s1/resourceGroups/rg");
+        assert_eq!(group.resource_type(), None);
+        assert_eq!(ResourceId::parse("/subscriptions/s1").unwrap().resource_group, None);
+        assert!(ResourceId::parse("/subscriptions/s1/resourceGroups/rg/providers/Microsoft.Network/virtualNetworks").is_err());
+        assert!(ResourceId::parse("/subscriptions/s1/resourceGroups/rg/providers/Microsoft.Compute/virtualMachines/vm/providers/Microsoft.Insights/diagnosticSettings/d").is_err());
+        assert!(ResourceId::parse("/resourceGroups/rg").is_err());
+        assert!(ResourceId::looks_like(text));
+        assert!(!ResourceId::looks_like("12345"));
+
+        let mut target = Target { subscription: Some("S1".to_string()), ..Target::default() };
+        target.apply_id(&id).unwrap();
+        assert_eq!(target.rg.as_deref(), Some("rg-coredns-challenge"));
+        assert_eq!(target.name.as_deref(), Some("nic1/ipconfig1"));
+        let mut conflicting = Target { rg: Some("other".to_string()), ..Target::default() };
+        assert!(conflicting.apply_id(&id).is_err());
+
+        let args = vec![text.to_string(), "properties.privateIPAddress".to_string()];
+        let invocation = Invocation::from_legacy(&args, Target::default(), |_| false).unwrap();
+        assert_eq!(invocation.command, Command::Get);
+        assert_eq!(invocation.command_line(), format!("get {} properties.privateIPAddress", text));
+    }
+
+    #[tokio::test]
+    async fn test_expand_references() {
+        let vm_id = "/subscriptions/s/resourceGroups/rg/providers/Microsoft.Compute/virtualMachines/vm1";
+        let nic_id = "/subscriptions/s/resourceGroups/rg/providers/Microsoft.Network/networkInterfaces/nic1";
+        let subnet_id = "/subscriptions/s/resourceGroups/rg/providers/Microsoft.Network/virtualNetworks/vnet/subnets/default";
+        let gone_id = "/subscriptions/s/resourceGroups/rg/providers/Microsoft.Compute/disks/gone";
+        let resources: HashMap<String, Value> = [
+            (nic_id.to_lowercase(), serde_json::json!({ "id": nic_id, "properties": {
+                "virtualMachine": { "id": vm_id },
+                "ipConfigurations": [{ "id": format!("{}/ipConfigurations/ipconfig1", nic_id), "properties": { "subnet": { "id": subnet_id } } }],
+            } })),
+            (subnet_id.to_lowercase(), serde_json::json!({ "id": subnet_id, "properties": { "addressPrefix": "10.0.1.0/24" } })),
+        ].into_iter().collect();
+
+        let vm = serde_json::json!({ "id": vm_id, "properties": {
+            "networkProfile": { "networkInterfaces": [{ "id": nic_id }, { "id": nic_id.to_uppercase() }] },
+            "storageProfile": { "osDisk": { "managedDisk": { "id": gone_id } } },
+            "extensions": [{ "id": format!("{}/extensions/agent", vm_id) }],
+        } });
+        let mut found = Vec::new();
+        collect_references(&vm, vm_id, &mut found);
+        assert_eq!(found, [nic_id, gone_id]);
+
+        let calls = std::sync::Mutex::new(Vec::new());
+        let fetch = |id: String| {
+            calls.lock().unwrap().push(id.clone());
+            let resource = resources.get(&id.to_lowercase()).cloned();
+            async move { resource.ok_or(AzureSearchError::NotFound { message: format!("{} not found", id) }) }
+        };
+
+        // one hop reaches the NIC and the missing disk; two also reach the subnet, and never the VM again
+        let mut shallow = vm.clone();
+        expand_references(&mut shallow, 1, 4, fetch).await;
+        assert_eq!(shallow["_resolved"].as_object().unwrap().len(), 2);
+        assert!(shallow["_resolved"][gone_id]["error"].is_string());
+
+        calls.lock().unwrap().clear();
+        let mut deep = vm.clone();
+        expand_references(&mut deep, 2, 4, fetch).await;
+        assert_eq!(deep["_resolved"][subnet_id]["properties"]["addressPrefix"], "10.0.1.0/24");
+        assert_eq!(*calls.lock().unwrap(), [nic_id, gone_id, subnet_id]);
+    }
+
+    #[tokio::test]
+    async fn test_expand_references_to_unlisted_child_type() {
+        let subnet_id = "/subscriptions/s/resourceGroups/rg/providers/Microsoft.Network/virtualNetworks/vnet/subnets/default";
+        let provider = r#"{"resourceTypes":[{"resourceType":"virtualNetworks","apiVersions":["2023-05-01"]}]}"#;
+        let subnet = format!(r#"{{"id":"{}","properties":{{"addressPrefix":"10.0.1.0/24"}}}}"#, subnet_id);
+        let (base_url, requests) = serve_sequence(vec![("200 OK", vec![], provider), ("200 OK", vec![], &subnet)]).await;
+        let client = test_client(&base_url, RetryPolicy::default());
+        let aliases = AliasTable::builtin();
+
+        // the registry only lists virtualNetworks, so the subnet is fetched with its version
+        let mut nic = serde_json::json!({ "id": "/subscriptions/s/resourceGroups/rg/providers/Microsoft.Network/networkInterfaces/nic1",
+            "properties": { "ipConfigurations": [{ "properties": { "subnet": { "id": subnet_id } } }] } });
+        expand_references(&mut nic, 1, 4, |id| {
+            let (client, aliases) = (&client, &aliases);
+            async move { get_resource_by_id(client, aliases, &id).await }
+        }).await;
+        assert_eq!(nic["_resolved"][subnet_id]["properties"]["addressPrefix"], "10.0.1.0/24");
+
+        let requests = requests.await.unwrap();
+        assert!(requests[1].starts_with(&format!("GET {}?api-version=2023-05-01 ", subnet_id)));
+    }
+
+    #[test]
+    fn test_resource_graph() {
+        let rg = "/subscriptions/s/resourceGroups/rg-coredns-challenge/providers";
+        let lb = format!("{}/Microsoft.Network/loadBalancers/lb-coredns", rg);
+        let pip = format!("{}/Microsoft.Network/publicIPAddresses/pip-coredns", rg);
+        let vnet = format!("{}/Microsoft.Network/virtualNetworks/vnet-coredns", rg);
+        let aci = format!("{}/Microsoft.ContainerInstance/containerGroups/coredns-1", rg);
+        let nsg = "/subscriptions/s/resourceGroups/shared/providers/Microsoft.Network/networkSecurityGroups/nsg-shared";
+        let resources = vec![
+            serde_json::json!({ "id": lb, "properties": {
+                "frontendIPConfigurations": [{ "id": format!("{}/frontendIPConfigurations/fe", lb), "properties": { "publicIPAddress": { "id": pip } } }],
+                "backendAddressPools": [{ "id": format!("{}/backendAddressPools/be", lb), "properties": { "virtualNetwork": { "id": vnet } } }],
+            } }),
+            serde_json::json!({ "id": pip, "properties": { "ipConfiguration": { "id": format!("{}/frontendIPConfigurations/fe", lb) } } }),
+            serde_json::json!({ "id": vnet, "properties": { "subnets": [{ "id": format!("{}/subnets/aci", vnet), "properties": { "networkSecurityGroup": { "id": nsg } } }] } }),
+            serde_json::json!({ "id": aci, "properties": { "subnetIds": [{ "id": format!("{}/subnets/aci", vnet) }] } }),
+        ];
+
+        let graph = ResourceGraph::build(&resources);
+        let names: Vec<&str> = graph.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, ["lb-coredns", "pip-coredns", "vnet-coredns", "coredns-1", "nsg-shared"]);
+        assert!(graph.nodes[4].external);
+        // child references point at their top-level resource, the pip -> lb back reference included
+        assert_eq!(graph.edges, [(0, 1), (0, 2), (1, 0), (2, 4), (3, 2)]);
+        assert_eq!(graph.owner_of(&format!("{}/SUBNETS/aci/ipConfigurations/x", vnet.to_uppercase())), Some(2));
+        assert_eq!(graph.owner_of(&format!("{}-2/subnets/aci", vnet)), None);
+
+        let json = graph.to_json();
+        assert_eq!(json["adjacency"][&aci], serde_json::json!([vnet]));
+        assert_eq!(json["nodes"][4]["type"], "Microsoft.Network/networkSecurityGroups");
+
+        // from the container group only the vnet and its nsg are reachable
+        let start = graph.select("CoreDNS-1", None).unwrap();
+        let sub = graph.reachable_from(start);
+        assert_eq!(sub.nodes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>(), ["vnet-coredns", "coredns-1", "nsg-shared"]);
+        assert_eq!(sub.edges, [(0, 2), (1, 0)]);
+        assert!(graph.select("missing", None).is_err());
+
+        let dot = sub.to_dot();
+        assert!(dot.starts_with("digraph resources {"));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\";", aci, vnet)));
+        assert!(dot.contains("label=\"nsg-shared\\nnetworkSecurityGroups\", style=dashed"));
+        let mermaid = sub.to_mermaid();
+        assert!(mermaid.contains("  n1[\"coredns-1<br/>containerGroups\"]\n"));
+        assert!(mermaid.contains("  n2([\"nsg-shared<br/>networkSecurityGroups\"])\n"));
+        assert!(mermaid.contains("  n1 --> n0\n"));
+    }
+
+    #[test]
+    fn test_find_by_name() {
+        let resources: Vec<Value> = ["coredns-1", "coredns-2", "CoreDNS", "vnet-coredns", "pip-coredns-1"].iter()
+            .map(|name| serde_json::json!({ "name": name }))
+            .collect();
+        let names = |pattern: &str| find_by_name(&resources, pattern).iter().map(|r| r["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+
+        // an exact name wins over the longer names it prefixes
+        assert_eq!(names("coredns"), ["CoreDNS"]);
+        assert_eq!(names("coredns-"), ["coredns-1", "coredns-2"]);
+        assert_eq!(names("VNET"), ["vnet-coredns"]);
+        assert_eq!(names("*coredns-1"), ["coredns-1", "pip-coredns-1"]);
+        assert_eq!(names("coredns-?"), ["coredns-1", "coredns-2"]);
+        assert!(names("missing").is_empty());
+
+        let listed = serde_json::json!({
+            "id": "/subscriptions/s/resourceGroups/rg-dns/providers/Microsoft.ContainerInstance/containerGroups/coredns-1",
+            "name": "coredns-1",
+            "type": "Microsoft.ContainerInstance/containerGroups",
+            "location": "westeurope",
+            "properties": {},
+        });
+        assert_eq!(find_candidate(&listed), serde_json::json!({
+            "name": "coredns-1",
+            "resourceGroup": "rg-dns",
+            "type": "Microsoft.ContainerInstance/containerGroups",
+            "id": "/subscriptions/s/resourceGroups/rg-dns/providers/Microsoft.ContainerInstance/containerGroups/coredns-1",
+        }));
     }
-}
\ No newline at end of file
+}